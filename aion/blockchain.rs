@@ -19,16 +19,16 @@
  *
  ******************************************************************************/
 
-use std::str::{FromStr, from_utf8};
+use std::str::FromStr;
 use std::{io, fs};
-use std::io::{BufReader, BufRead};
+use std::io::{BufReader, BufRead, Read};
 use std::time::{Instant, Duration};
 use std::thread::sleep;
 use std::sync::Arc;
 use rustc_hex::FromHex;
 use bytes::ToPretty;
-use rlp::PayloadInfo;
 use acore::service::ClientService;
+use acore::blockchain::{read_raw_block, ExportReader, ExportWriter, EXPORT_MAGIC};
 use acore::client::{DatabaseCompactionProfile, VMType, BlockImportError, BlockChainClient, BlockId};
 use acore::error::ImportError;
 use acore::miner::Miner;
@@ -54,7 +54,11 @@ impl MillisecondDuration for Duration {
 
 #[derive(Debug, PartialEq)]
 pub enum DataFormat {
+    /// One hex encoded block RLP per line. Hex dumps carry no genesis hash or checksum, so
+    /// they are imported unchecked.
     Hex,
+    /// Block RLPs framed by a header with the genesis hash and a checksum trailer. Headerless
+    /// dumps written by earlier releases are still imported, unchecked.
     Binary,
 }
 
@@ -226,12 +230,16 @@ fn execute_import(cmd: ImportBlockchain) -> Result<(), String> {
             first_read = instream
                 .read(&mut first_bytes)
                 .map_err(|_| "Error reading from the file/stream.")?;
-            match first_bytes[0] {
-                0xf9 => DataFormat::Binary,
-                _ => DataFormat::Hex,
+            // raw rlp streams without a dump header are detected as well
+            if first_bytes[0] == EXPORT_MAGIC[0] || first_bytes[0] == 0xf9 {
+                DataFormat::Binary
+            } else {
+                DataFormat::Hex
             }
         }
     };
+    first_bytes.truncate(first_read);
+    let instream = io::Cursor::new(first_bytes).chain(instream);
 
     let do_import = |bytes| {
         while client.queue_info().is_full() {
@@ -249,45 +257,38 @@ fn execute_import(cmd: ImportBlockchain) -> Result<(), String> {
 
     match format {
         DataFormat::Binary => {
-            loop {
-                let mut bytes = if first_read > 0 {
-                    first_bytes.clone()
-                } else {
-                    vec![0; READAHEAD_BYTES]
-                };
-                let n = if first_read > 0 {
-                    first_read
-                } else {
-                    instream
-                        .read(&mut bytes)
-                        .map_err(|_| "Error reading from the file/stream.")?
-                };
-                if n == 0 {
-                    break;
+            let mut instream = BufReader::new(instream);
+            let headerless = instream
+                .fill_buf()
+                .map_err(|_| "Error reading from the file/stream.")?
+                .first()
+                .map_or(false, |byte| *byte != EXPORT_MAGIC[0]);
+            if headerless {
+                warn!(
+                    target: "import",
+                    "Block dump has no header, importing it without genesis and checksum checks."
+                );
+                let mut index = 0;
+                while let Some(bytes) = read_raw_block(&mut instream, index)
+                    .map_err(|e| format!("Invalid block dump: {}", e))?
+                {
+                    do_import(bytes)?;
+                    index += 1;
+                }
+            } else {
+                let mut reader = ExportReader::new(instream, &genesis_hash)
+                    .map_err(|e| format!("Invalid block dump: {}", e))?;
+                while let Some(bytes) = reader
+                    .next_block()
+                    .map_err(|e| format!("Invalid block dump: {}", e))?
+                {
+                    do_import(bytes)?;
                 }
-                first_read = 0;
-                let s = PayloadInfo::from(&bytes)
-                    .map_err(|e| format!("Invalid RLP in the file/stream: {:?}", e))?
-                    .total();
-                bytes.resize(s, 0);
-                instream
-                    .read_exact(&mut bytes[n..])
-                    .map_err(|_| "Error reading from the file/stream.")?;
-                do_import(bytes)?;
             }
         }
         DataFormat::Hex => {
             for line in BufReader::new(instream).lines() {
                 let s = line.map_err(|_| "Error reading from the file/stream.")?;
-                let s = if first_read > 0 {
-                    from_utf8(&first_bytes)
-                        .map_err(|e| format!("Error reading : {}", e))?
-                        .to_owned()
-                        + &(s[..])
-                } else {
-                    s
-                };
-                first_read = 0;
                 let bytes = s.from_hex().map_err(|_| "Invalid hex in file/stream.")?;
                 do_import(bytes)?;
             }
@@ -430,21 +431,35 @@ fn execute_export(cmd: ExportBlockchain) -> Result<(), String> {
         ));
     }
 
-    for i in from..(to + 1) {
+    let blocks = (from..(to + 1)).map(|i| {
         if i % 10000 == 0 {
             info!(target:"export","#{}", i);
         }
-        let b = client
+        client
             .block(BlockId::Number(i))
-            .ok_or("Error exporting incomplete chain")?
-            .into_inner();
-        match format {
-            DataFormat::Binary => {
-                out.write(&b)
+            .map(|b| b.into_inner())
+            .ok_or("Error exporting incomplete chain")
+    });
+
+    match format {
+        DataFormat::Binary => {
+            let genesis_hash = client
+                .block_hash(BlockId::Earliest)
+                .ok_or("Genesis block could not be found")?;
+            let mut writer = ExportWriter::new(out, &genesis_hash)
+                .map_err(|e| format!("Couldn't write to stream. Cause: {}", e))?;
+            for b in blocks {
+                writer
+                    .write_block(&b?)
                     .map_err(|e| format!("Couldn't write to stream. Cause: {}", e))?;
             }
-            DataFormat::Hex => {
-                out.write_fmt(format_args!("{}", b.pretty()))
+            writer
+                .finish()
+                .map_err(|e| format!("Couldn't write to stream. Cause: {}", e))?;
+        }
+        DataFormat::Hex => {
+            for b in blocks {
+                out.write_fmt(format_args!("{}", b?.pretty()))
                     .map_err(|e| format!("Couldn't write to stream. Cause: {}", e))?;
             }
         }
@@ -23,17 +23,19 @@
 //! Blockchain database.
 
 use std::collections::{HashMap, hash_map};
+use std::io::{self, Read, Write};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::mem;
 use itertools::Itertools;
 use bloomchain as bc;
 use heapsize::HeapSizeOf;
-use aion_types::{H256, U256};
+use aion_types::{Address, H256, U256};
 use ethbloom::Bloom;
 use parking_lot::{Mutex, RwLock};
 use bytes::Bytes;
 use rlp::*;
-use rlp_compress::{compress, decompress, blocks_swapper};
+use rlp_compress::{compress, decompress, blocks_swapper, DictionarySwapper};
 use header::*;
 use transaction::*;
 use views::*;
@@ -43,19 +45,27 @@ use blooms::{BloomGroup, GroupPosition};
 use blockchain::best_block::{BestBlock, BestAncientBlock};
 use blockchain::block_info::{BlockInfo, BlockLocation, BranchBecomingCanonChainData};
 use blockchain::extras::{
-    BlockReceipts, BlockDetails, TransactionAddress, EPOCH_KEY_PREFIX, EpochTransitions,
+    BestBlockHint, BlockReceipts, BlockDetails, ExtraKey, TransactionAddress, EPOCH_KEY_PREFIX,
+    EpochTransitions,
 };
 use types::blockchain_info::BlockChainInfo;
 use types::tree_route::TreeRoute;
 use blockchain::update::ExtrasUpdate;
-use blockchain::{CacheSize, ImportRoute, Config};
+use blockchain::export::{ExportReader, ExportWriter, ExportSummary, ExportError};
+use blockchain::{CacheSize, ImportRoute, Config, CompressionMode};
+#[cfg(any(test, feature = "debug-cache"))]
+use blockchain::CacheKeys;
 use db::{self, Writable, Readable, CacheUpdatePolicy};
 use cache_manager::CacheManager;
 use encoded;
 use engines::epoch::{Transition as EpochTransition, PendingTransition as PendingEpochTransition};
 use rayon::prelude::*;
+use rayon::ThreadPool;
 use ansi_term::Colour;
-use kvdb::{DBTransaction, KeyValueDB};
+use kvdb::{DBTransaction, KeyValueDB, Error as DbError};
+use error::{Error, BlockError, BlockImportError, ImportError};
+use unexpected::{Mismatch, OutOfBounds};
+use triehash::ordered_trie_root;
 
 extern crate blake2b;
 
@@ -127,6 +137,17 @@ pub trait BlockProvider {
         self.block_details(hash).map(|details| details.number)
     }
 
+    /// Get the header RLP of the canonical block with given number.
+    fn header_at(&self, number: BlockNumber) -> Option<encoded::Header> {
+        self.block_hash(number)
+            .and_then(|hash| self.block_header_data(&hash))
+    }
+
+    /// Get raw data of the canonical block with given number.
+    fn block_at(&self, number: BlockNumber) -> Option<encoded::Block> {
+        self.block_hash(number).and_then(|hash| self.block(&hash))
+    }
+
     /// Get transaction with given transaction hash.
     fn transaction(&self, address: &TransactionAddress) -> Option<LocalizedTransaction> {
         self.block_body(&address.block_hash).and_then(|body| {
@@ -209,6 +230,22 @@ impl bc::group::BloomGroupDatabase for BlockChain {
     }
 }
 
+/// Bloom of the given logs, accumulating the address and topics of each of them.
+/// This is the bloom a receipt carrying these logs would have.
+pub fn bloom_for_logs(logs: &[LogEntry]) -> Bloom {
+    logs.iter().fold(Bloom::default(), |b, log| &b | &log.bloom())
+}
+
+/// Trie root of the given receipts, as committed to by a block header.
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    ordered_trie_root(receipts.iter().map(|r| r.simple_receipt().rlp_bytes()))
+}
+
+/// Trie root of the given transactions, as committed to by a block header.
+pub fn transactions_root(txs: &[UnverifiedTransaction]) -> H256 {
+    ordered_trie_root(txs.iter().map(|tx| tx.rlp_bytes()))
+}
+
 /// Structure providing fast access to blockchain data.
 ///
 /// **Does not do input data verification.**
@@ -241,8 +278,29 @@ pub struct BlockChain {
 
     pending_best_block: RwLock<Option<BestBlock>>,
     pending_block_hashes: RwLock<HashMap<BlockNumber, H256>>,
+    // canonical numbers dropped by `rewind_to`, evicted from the cache on commit
+    pending_removed_block_hashes: RwLock<Vec<BlockNumber>>,
     pending_block_details: RwLock<HashMap<H256, BlockDetails>>,
     pending_transaction_addresses: RwLock<HashMap<H256, Option<TransactionAddress>>>,
+    // cached entries of deleted data, evicted on commit once the deletion is written
+    pending_invalidations: RwLock<Vec<CacheId>>,
+
+    // number of blocks scanned in parallel by `logs`
+    log_scan_chunk_size: usize,
+    // dedicated pool for `logs` queries, the global rayon pool is used if `None`
+    log_scan_pool: RwLock<Option<Arc<ThreadPool>>>,
+    // called when `insert_unordered_block` closes the gap after the first block sequence
+    gap_closed_listeners: RwLock<Vec<Box<Fn() + Send + Sync>>>,
+    verify_receipt_blooms: bool,
+    verify_receipts_root: bool,
+    verify_transactions_root: bool,
+    compression: CompressionMode,
+    // custom compression dictionary, the built-in one is used if `None`
+    swapper: Option<DictionarySwapper>,
+    // set when the database was created with another compression dictionary
+    dictionary_mismatch: bool,
+    best_block_fast_path: bool,
+    receipts_retained_depth: u64,
 }
 
 impl BlockProvider for BlockChain {
@@ -265,16 +323,7 @@ impl BlockProvider for BlockChain {
 
     /// Get raw block data
     fn block(&self, hash: &H256) -> Option<encoded::Block> {
-        match (self.block_header_data(hash), self.block_body(hash)) {
-            (Some(header), Some(body)) => {
-                let mut block = RlpStream::new_list(2);
-                let body_rlp = body.rlp();
-                block.append_raw(header.rlp().as_raw(), 1);
-                block.append_raw(body_rlp.at(0).as_raw(), 1);
-                Some(encoded::Block::new(block.out()))
-            }
-            _ => None,
-        }
+        self.block_rlp(hash).map(encoded::Block::new)
     }
 
     /// Get block header data
@@ -288,7 +337,7 @@ impl BlockProvider for BlockChain {
         }
 
         // Check if it's the best block
-        {
+        if self.best_block_fast_path {
             let best_block = self.best_block.read();
             if &best_block.hash == hash {
                 return Some(encoded::Header::new(
@@ -303,9 +352,10 @@ impl BlockProvider for BlockChain {
             .get(db::COL_HEADERS, hash)
             .expect("Low level database error. Some issue with disk?");
 
+        // decompression leaves raw rlp untouched, so entries stored in any mode can be read
         let result = match opt {
             Some(b) => {
-                let bytes = decompress(&b, blocks_swapper()).into_vec();
+                let bytes = self.decompress_block_data(&b);
                 let mut write = self.block_headers.write();
                 write.insert(*hash, bytes.clone());
                 Some(encoded::Header::new(bytes))
@@ -328,7 +378,7 @@ impl BlockProvider for BlockChain {
         }
 
         // Check if it's the best block
-        {
+        if self.best_block_fast_path {
             let best_block = self.best_block.read();
             if &best_block.hash == hash {
                 return Some(encoded::Body::new(Self::block_to_body(&best_block.block)));
@@ -341,9 +391,12 @@ impl BlockProvider for BlockChain {
             .get(db::COL_BODIES, hash)
             .expect("Low level database error. Some issue with disk?");
 
+        // decompression leaves raw rlp untouched, so entries stored in any mode can be read.
+        // an empty entry marks a block stored by `insert_header` without its body.
         let result = match opt {
+            Some(ref b) if b.is_empty() => return None,
             Some(b) => {
-                let bytes = decompress(&b, blocks_swapper()).into_vec();
+                let bytes = self.decompress_block_data(&b);
                 let mut write = self.block_bodies.write();
                 write.insert(*hash, bytes.clone());
                 Some(encoded::Body::new(bytes))
@@ -406,6 +459,19 @@ impl BlockProvider for BlockChain {
         to_block: BlockNumber,
     ) -> Vec<BlockNumber>
     {
+        if from_block > to_block {
+            debug!(
+                target: "blockchain",
+                "blocks_with_bloom called with inverted range {}..{}",
+                from_block,
+                to_block
+            );
+            return Vec::new();
+        }
+        let to_block = ::std::cmp::min(to_block, self.best_block_number());
+        if from_block > to_block {
+            return Vec::new();
+        }
         let range = from_block as bc::Number..to_block as bc::Number;
         let chain = bc::group::BloomGroupChain::new(self.blooms_config, self);
         chain
@@ -428,72 +494,87 @@ impl BlockProvider for BlockChain {
         // sort in reverse order
         blocks.sort_by(|a, b| b.cmp(a));
 
-        let mut logs = blocks
-            .chunks(128)
-            .flat_map(move |blocks_chunk| {
-                blocks_chunk
-                    .into_par_iter()
-                    .filter_map(|number| self.block_hash(*number).map(|hash| (*number, hash)))
-                    .filter_map(|(number, hash)| {
-                        self.block_receipts(&hash)
-                            .map(|r| (number, hash, r.receipts))
-                    })
-                    .filter_map(|(number, hash, receipts)| {
-                        self.block_body(&hash)
-                            .map(|ref b| (number, hash, receipts, b.transaction_hashes()))
-                    })
-                    .flat_map(|(number, hash, mut receipts, mut hashes)| {
-                        if receipts.len() != hashes.len() {
-                            warn!(
-                                target: "blockchain",
-                                "Block {} ({}) has different number of receipts ({}) to \
-                                 transactions ({}). Database corrupt?",
-                                number,
-                                hash,
-                                receipts.len(),
-                                hashes.len()
-                            );
-                            assert!(false);
-                        }
-                        let mut log_index = receipts
-                            .iter()
-                            .fold(0, |sum, receipt| sum + receipt.logs().len());
-
-                        let receipts_len = receipts.len();
-                        hashes.reverse();
-                        receipts.reverse();
-                        receipts
-                            .into_iter()
-                            .map(|receipt| receipt.logs().clone())
-                            .zip(hashes)
-                            .enumerate()
-                            .flat_map(move |(index, (mut logs, tx_hash))| {
-                                let current_log_index = log_index;
-                                let no_of_logs = logs.len();
-                                log_index -= no_of_logs;
-
-                                logs.reverse();
-                                logs.into_iter().enumerate().map(move |(i, log)| {
-                                    LocalizedLogEntry {
-                                        entry: log.clone(),
-                                        block_hash: hash,
-                                        block_number: number,
-                                        transaction_hash: tx_hash,
-                                        // iterating in reverse order
-                                        transaction_index: receipts_len - index - 1,
-                                        transaction_log_index: no_of_logs - i - 1,
-                                        log_index: current_log_index - i - 1,
-                                    }
+        let chunk_size = self.log_scan_chunk_size;
+        let limit = limit.unwrap_or(::std::usize::MAX);
+        let scan = move || {
+            // matching logs collected so far, remaining chunks are skipped once it reaches `limit`
+            let collected = AtomicUsize::new(0);
+            blocks
+                .chunks(chunk_size)
+                .take_while(|_| collected.load(AtomicOrdering::SeqCst) < limit)
+                .flat_map(|blocks_chunk| {
+                    let chunk_logs = blocks_chunk
+                        .into_par_iter()
+                        .filter_map(|number| self.block_hash(*number).map(|hash| (*number, hash)))
+                        .filter_map(|(number, hash)| {
+                            self.block_receipts(&hash)
+                                .map(|r| (number, hash, r.receipts))
+                        })
+                        .filter_map(|(number, hash, receipts)| {
+                            self.block_body(&hash)
+                                .map(|ref b| (number, hash, receipts, b.transaction_hashes()))
+                        })
+                        .flat_map(|(number, hash, mut receipts, mut hashes)| {
+                            if receipts.len() != hashes.len() {
+                                warn!(
+                                    target: "blockchain",
+                                    "Block {} ({}) has different number of receipts ({}) to \
+                                     transactions ({}). Database corrupt?",
+                                    number,
+                                    hash,
+                                    receipts.len(),
+                                    hashes.len()
+                                );
+                                assert!(false);
+                            }
+                            let mut log_index = receipts
+                                .iter()
+                                .fold(0, |sum, receipt| sum + receipt.logs().len());
+
+                            let receipts_len = receipts.len();
+                            hashes.reverse();
+                            receipts.reverse();
+                            receipts
+                                .into_iter()
+                                .map(|receipt| receipt.logs().clone())
+                                .zip(hashes)
+                                .enumerate()
+                                .flat_map(move |(index, (mut logs, tx_hash))| {
+                                    let current_log_index = log_index;
+                                    let no_of_logs = logs.len();
+                                    log_index -= no_of_logs;
+
+                                    logs.reverse();
+                                    logs.into_iter().enumerate().map(move |(i, log)| {
+                                        LocalizedLogEntry {
+                                            entry: log.clone(),
+                                            block_hash: hash,
+                                            block_number: number,
+                                            transaction_hash: tx_hash,
+                                            // iterating in reverse order
+                                            transaction_index: receipts_len - index - 1,
+                                            transaction_log_index: no_of_logs - i - 1,
+                                            log_index: current_log_index - i - 1,
+                                        }
+                                    })
                                 })
-                            })
-                            .filter(|log_entry| matches(&log_entry.entry))
-                            .take(limit.unwrap_or(::std::usize::MAX))
-                            .collect::<Vec<_>>()
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .take(limit.unwrap_or(::std::usize::MAX))
-            .collect::<Vec<LocalizedLogEntry>>();
+                                .filter(|log_entry| matches(&log_entry.entry))
+                                .take(limit)
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+                    collected.fetch_add(chunk_logs.len(), AtomicOrdering::SeqCst);
+                    chunk_logs
+                })
+                .take(limit)
+                .collect::<Vec<LocalizedLogEntry>>()
+        };
+
+        let pool = self.log_scan_pool.read().clone();
+        let mut logs = match pool {
+            Some(pool) => pool.install(scan),
+            None => scan(),
+        };
         logs.reverse();
         logs
     }
@@ -570,7 +651,41 @@ impl<'a> Iterator for EpochTransitionIter<'a> {
 }
 
 impl BlockChain {
+    /// Create new instance of blockchain from given Genesis, refusing an invalid configuration
+    /// and a database which was initialized with a different genesis block or compression
+    /// dictionary.
+    pub fn new_checked(
+        config: Config,
+        genesis: &[u8],
+        db: Arc<KeyValueDB>,
+    ) -> Result<BlockChain, Error>
+    {
+        config.validate()?;
+        let expected = BlockView::new(genesis).hash();
+        let stored: Option<H256> = db.read(db::COL_EXTRA, &0u64);
+        if let Some(found) = stored {
+            if found != expected {
+                return Err(Error::GenesisMismatch(Mismatch {
+                    expected: expected,
+                    found: found,
+                }));
+            }
+            // existing entries can only be decompressed with the dictionary they were stored with
+            let configured = config
+                .compression_dictionary
+                .as_ref()
+                .map(|dictionary| Self::dictionary_fingerprint(dictionary));
+            if Self::stored_dictionary_fingerprint(&*db) != configured {
+                return Err(Error::DictionaryMismatch);
+            }
+        }
+        Ok(Self::new(config, genesis, db))
+    }
+
     /// Create new instance of blockchain from given Genesis.
+    ///
+    /// Panics if the configured compression dictionary is too large. Opening a database created
+    /// with another dictionary is only flagged, see `new_checked` for the strict variant.
     pub fn new(config: Config, genesis: &[u8], db: Arc<KeyValueDB>) -> BlockChain {
         // 400 is the avarage size of the key
         let cache_man = CacheManager::new(config.pref_cache_size, config.max_cache_size, 400);
@@ -594,17 +709,50 @@ impl BlockChain {
             cache_man: Mutex::new(cache_man),
             pending_best_block: RwLock::new(None),
             pending_block_hashes: RwLock::new(HashMap::new()),
+            pending_removed_block_hashes: RwLock::new(Vec::new()),
             pending_block_details: RwLock::new(HashMap::new()),
             pending_transaction_addresses: RwLock::new(HashMap::new()),
+            pending_invalidations: RwLock::new(Vec::new()),
+            log_scan_chunk_size: ::std::cmp::max(config.log_scan_chunk_size, 1),
+            log_scan_pool: RwLock::new(None),
+            gap_closed_listeners: RwLock::new(Vec::new()),
+            verify_receipt_blooms: config.verify_receipt_blooms,
+            verify_receipts_root: config.verify_receipts_root,
+            verify_transactions_root: config.verify_transactions_root,
+            compression: config.compression,
+            swapper: config
+                .compression_dictionary
+                .as_ref()
+                .map(|dictionary| DictionarySwapper::new(dictionary)),
+            dictionary_mismatch: false,
+            best_block_fast_path: config.best_block_fast_path,
+            receipts_retained_depth: config.receipts_retained_depth,
         };
+        let dictionary_fingerprint = config
+            .compression_dictionary
+            .as_ref()
+            .map(|dictionary| Self::dictionary_fingerprint(dictionary));
 
         // load best block
         let best_block_hash = match bc
             .db
-            .get(db::COL_EXTRA, b"best")
+            .get(db::COL_EXTRA, ExtraKey::Best.as_bytes())
             .expect("EXTRA db not be found")
         {
-            Some(best) => H256::from_slice(&best),
+            Some(best) => {
+                if Self::stored_dictionary_fingerprint(&*bc.db) != dictionary_fingerprint {
+                    // stored entries can't be reliably decompressed any more, at least avoid
+                    // mixing in entries compressed with the new dictionary
+                    warn!(
+                        target: "blockchain",
+                        "Database was created with another compression dictionary, storing new \
+                         blocks uncompressed."
+                    );
+                    bc.dictionary_mismatch = true;
+                    bc.compression = CompressionMode::None;
+                }
+                H256::from_slice(&best)
+            }
             None => {
                 // best block does not exist
                 // we need to insert genesis into the cache
@@ -626,7 +774,16 @@ impl BlockChain {
                 batch.write(db::COL_EXTRA, &hash, &details);
                 batch.write(db::COL_EXTRA, &header.number(), &hash);
 
-                batch.put(db::COL_EXTRA, b"best", &hash);
+                batch.put(db::COL_EXTRA, ExtraKey::Best.as_bytes(), &hash);
+                Self::write_best_block_hint(
+                    &mut batch,
+                    hash,
+                    details.number,
+                    details.total_difficulty,
+                );
+                if let Some(ref fingerprint) = dictionary_fingerprint {
+                    batch.put(db::COL_EXTRA, ExtraKey::Dictionary.as_bytes(), fingerprint);
+                }
                 bc.db
                     .write(batch)
                     .expect("Low level database error. Some issue with disk?");
@@ -635,14 +792,31 @@ impl BlockChain {
         };
 
         {
-            // Fetch best block details
-            let best_block_number = bc
-                .block_number(&best_block_hash)
-                .expect("best block not found, db may crashed");
-            let best_block_total_difficulty = bc
-                .block_details(&best_block_hash)
-                .expect("best block not found, db may crashed")
-                .total_difficulty;
+            // Fetch best block details, unless the hint was written along with the best block
+            let best_block_hint = bc
+                .db
+                .get(db::COL_EXTRA, ExtraKey::BestHint.as_bytes())
+                .expect("EXTRA db not be found")
+                .and_then(|raw| UntrustedRlp::new(&raw).as_val::<BestBlockHint>().ok());
+            let (best_block_number, best_block_total_difficulty) = match best_block_hint {
+                Some(ref hint) if hint.hash == best_block_hash => {
+                    (hint.number, hint.total_difficulty)
+                }
+                hint => {
+                    if let Some(hint) = hint {
+                        warn!(
+                            target: "blockchain",
+                            "Best block hint {} doesn't match best block {}, ignoring it.",
+                            hint.hash,
+                            best_block_hash
+                        );
+                    }
+                    let details = bc
+                        .block_details(&best_block_hash)
+                        .expect("best block not found, db may crashed");
+                    (details.number, details.total_difficulty)
+                }
+            };
             let best_block_rlp = bc
                 .block(&best_block_hash)
                 .expect("best block not found, db may crashed")
@@ -651,12 +825,12 @@ impl BlockChain {
 
             let raw_first = bc
                 .db
-                .get(db::COL_EXTRA, b"first")
+                .get(db::COL_EXTRA, ExtraKey::First.as_bytes())
                 .expect("EXTRA db not be found")
                 .map(|v| v.into_vec());
             let mut best_ancient = bc
                 .db
-                .get(db::COL_EXTRA, b"ancient")
+                .get(db::COL_EXTRA, ExtraKey::Ancient.as_bytes())
                 .expect("EXTRA db not be found")
                 .map(|h| H256::from_slice(&h));
             let best_ancient_number;
@@ -693,7 +867,7 @@ impl BlockChain {
                     if hash != bc.genesis_hash() {
                         trace!(target:"blockchain","First block calculated: {:?}", hash);
                         let mut batch = DBTransaction::new();
-                        batch.put(db::COL_EXTRA, b"first", &hash);
+                        batch.put(db::COL_EXTRA, ExtraKey::First.as_bytes(), &hash);
                         db.write(batch).expect("Low level database error.");
                         bc.first_block = Some(hash);
                     }
@@ -733,6 +907,167 @@ impl BlockChain {
             .map_or(false, |d| d.children.contains(hash))
     }
 
+    /// Returns true if the block was stored with `insert_header` and hasn't been imported in
+    /// full yet: such blocks have no receipts and are never part of the canonical chain.
+    fn is_header_only(&self, hash: &H256) -> bool {
+        !self
+            .db
+            .exists_with_cache(db::COL_EXTRA, &self.block_receipts, hash)
+            && !self.contains_canonical(hash)
+    }
+
+    /// Returns true if the block with given hash is part of the canonical chain.
+    ///
+    /// Unlike `is_known`, which is true for any imported block, this is false for blocks
+    /// which are only stored on a side chain.
+    pub fn contains_canonical(&self, hash: &H256) -> bool {
+        self.block_number(hash)
+            .and_then(|number| self.block_hash(number))
+            .map_or(false, |canonical| canonical == *hash)
+    }
+
+    /// Returns whether each of the given blocks is known, in the order of `hashes`.
+    /// The details cache is locked once for all of them, then misses are looked up
+    /// in the database.
+    pub fn are_known(&self, hashes: &[H256]) -> Vec<bool> {
+        let mut known: Vec<bool> = {
+            let read = self.block_details.read();
+            hashes.iter().map(|hash| read.contains_key(hash)).collect()
+        };
+        for (index, hash) in hashes.iter().enumerate() {
+            if !known[index] {
+                known[index] = self.db.exists::<BlockDetails, _>(db::COL_EXTRA, hash);
+            }
+        }
+        known
+    }
+
+    /// Returns the canonical hashes of blocks `[from, to]` in order, with `None` for numbers
+    /// without a canonical block. Like `are_known`, the hashes cache is locked once and
+    /// misses are looked up in the database.
+    pub fn canonical_range_hashes(&self, from: BlockNumber, to: BlockNumber) -> Vec<Option<H256>> {
+        if from > to {
+            return Vec::new();
+        }
+        let mut hashes: Vec<Option<H256>> = {
+            let read = self.block_hashes.read();
+            (from..=to).map(|number| read.get(&number).cloned()).collect()
+        };
+        for (number, hash) in (from..=to).zip(hashes.iter_mut()) {
+            if hash.is_none() {
+                *hash = self.db.read(db::COL_EXTRA, &number);
+            }
+        }
+        hashes
+    }
+
+    /// Checks that every canonical block in `[from, to]` is linked by its details to the
+    /// canonical block one number below, as the number to hash index and the parent pointers
+    /// can disagree after a crash. Returns the numbers where the link is broken, including
+    /// numbers without a canonical block.
+    pub fn verify_canonical_links(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<(), Vec<BlockNumber>>
+    {
+        if from > to {
+            return Ok(());
+        }
+        let first = from.saturating_sub(1);
+        let hashes = self.canonical_range_hashes(first, to);
+        let broken: Vec<BlockNumber> = (from..=to)
+            .filter(|&number| {
+                let hash = hashes[(number - first) as usize];
+                let linked = match hash {
+                    Some(_) if number == 0 => true,
+                    Some(hash) => {
+                        let parent = hashes[(number - 1 - first) as usize];
+                        self.block_details(&hash).map_or(false, |details| {
+                            details.number == number && Some(details.parent) == parent
+                        })
+                    }
+                    None => false,
+                };
+                !linked
+            })
+            .collect();
+        if broken.is_empty() {
+            Ok(())
+        } else {
+            Err(broken)
+        }
+    }
+
+    /// Returns true if a transaction with given hash is known,
+    /// without decoding its address.
+    pub fn has_transaction(&self, hash: &H256) -> bool {
+        self.db
+            .exists_with_cache(db::COL_EXTRA, &self.transaction_addresses, hash)
+    }
+
+    /// Returns the position of transaction `tx` within the body of block `block`,
+    /// or `None` if either is unknown or the transaction is not part of that block.
+    pub fn transaction_index_in(&self, block: &H256, tx: &H256) -> Option<usize> {
+        self.block_body(block)?
+            .transaction_hashes()
+            .into_iter()
+            .position(|hash| hash == *tx)
+    }
+
+    /// Run `logs` queries on the given thread pool instead of the global one,
+    /// so that heavy log scans can't starve other work. `None` restores the global pool.
+    pub fn set_log_scan_pool(&self, pool: Option<Arc<ThreadPool>>) {
+        *self.log_scan_pool.write() = pool;
+    }
+
+    /// Persist the number and total difficulty of the best block as a hint, so that the next
+    /// `new` can take them instead of reading the best block details. Must be written in the
+    /// same batch as the best block hash; a hint for another hash is ignored.
+    fn write_best_block_hint(
+        batch: &mut DBTransaction,
+        hash: H256,
+        number: BlockNumber,
+        total_difficulty: U256,
+    )
+    {
+        let hint = BestBlockHint {
+            hash: hash,
+            number: number,
+            total_difficulty: total_difficulty,
+        };
+        batch.put(db::COL_EXTRA, ExtraKey::BestHint.as_bytes(), &encode(&hint));
+    }
+
+    /// Run a bulk maintenance job, e.g. a reindex or a migration, writing its operations
+    /// in chunks so the pending transaction stays bounded. `f` does a unit of work on the
+    /// given batch per call and returns whether there is more to do. The batch is written
+    /// and replaced once it holds `ops_per_commit` operations, and after the last call.
+    /// Returns the number of writes.
+    pub fn with_chunked_batch<F>(&self, ops_per_commit: usize, mut f: F) -> usize
+    where F: FnMut(&mut DBTransaction) -> bool {
+        assert!(ops_per_commit > 0, "chunk size must be positive");
+        let mut commits = 0;
+        let mut batch = DBTransaction::new();
+        loop {
+            let more = f(&mut batch);
+            if batch.len() >= ops_per_commit || (!more && !batch.is_empty()) {
+                let chunk = mem::replace(&mut batch, DBTransaction::new());
+                self.db.write(chunk).expect("Low level database error.");
+                commits += 1;
+            }
+            if !more {
+                return commits;
+            }
+        }
+    }
+
+    /// Register a listener called once `insert_unordered_block` fills the gap
+    /// left by fast sync and clears the ancient block marker.
+    pub fn on_gap_closed(&self, listener: Box<Fn() + Send + Sync>) {
+        self.gap_closed_listeners.write().push(listener);
+    }
+
     /// Returns a tree route between `from` and `to`, which is a tuple of:
     ///
     /// - a vector of hashes of all blocks, ordered from `from` to `to`.
@@ -852,8 +1187,8 @@ impl BlockChain {
 
         assert!(self.pending_best_block.read().is_none());
 
-        let compressed_header = compress(block.header_rlp().as_raw(), blocks_swapper());
-        let compressed_body = compress(&Self::block_to_body(bytes), blocks_swapper());
+        let compressed_header = self.compress_block_data(block.header_rlp().as_raw());
+        let compressed_body = self.compress_block_data(&Self::block_to_body(bytes));
 
         // store block in db
         batch.put(db::COL_HEADERS, &hash, &compressed_header);
@@ -886,17 +1221,28 @@ impl BlockChain {
             );
 
             if is_ancient {
-                let mut best_ancient_block = self.best_ancient_block.write();
-                let ancient_number = best_ancient_block.as_ref().map_or(0, |b| b.number);
-                if self.block_hash(header.number() + 1).is_some() {
-                    batch.delete(db::COL_EXTRA, b"ancient");
-                    *best_ancient_block = None;
-                } else if header.number() > ancient_number {
-                    batch.put(db::COL_EXTRA, b"ancient", &hash);
-                    *best_ancient_block = Some(BestAncientBlock {
-                        hash: hash,
-                        number: header.number(),
-                    });
+                let gap_closed = {
+                    let mut best_ancient_block = self.best_ancient_block.write();
+                    let ancient_number = best_ancient_block.as_ref().map_or(0, |b| b.number);
+                    if self.block_hash(header.number() + 1).is_some() {
+                        batch.delete(db::COL_EXTRA, ExtraKey::Ancient.as_bytes());
+                        best_ancient_block.take().is_some()
+                    } else {
+                        if header.number() > ancient_number {
+                            batch.put(db::COL_EXTRA, ExtraKey::Ancient.as_bytes(), &hash);
+                            *best_ancient_block = Some(BestAncientBlock {
+                                hash: hash,
+                                number: header.number(),
+                            });
+                        }
+                        false
+                    }
+                };
+                // listeners run without the lock, so they may query the chain
+                if gap_closed {
+                    for listener in self.gap_closed_listeners.read().iter() {
+                        listener();
+                    }
                 }
             }
 
@@ -943,6 +1289,94 @@ impl BlockChain {
         }
     }
 
+    /// Store a block header without its body, for header-first sync.
+    ///
+    /// The header and its details are stored off the canonical chain, like a side branch,
+    /// and the body is marked as absent: `block_body` and `block` return `None` until
+    /// the body is supplied with `attach_body`. The parent must already be known.
+    ///
+    /// The block joins the canonical chain once it is imported in full with `insert_block`,
+    /// which completes a header-only block instead of treating it as already imported.
+    pub fn insert_header(
+        &self,
+        batch: &mut DBTransaction,
+        header_bytes: &[u8],
+    ) -> Result<(), BlockImportError>
+    {
+        let header: Header = UntrustedRlp::new(header_bytes)
+            .as_val()
+            .map_err(|e| BlockImportError::Other(format!("Invalid header: {}", e)))?;
+        let hash = header.hash();
+        if self.is_known(&hash) {
+            return Err(BlockImportError::Import(ImportError::AlreadyInChain));
+        }
+        let parent_hash = *header.parent_hash();
+        let mut parent_details = self
+            .block_details(&parent_hash)
+            .ok_or(BlockImportError::Block(BlockError::UnknownParent(parent_hash)))?;
+
+        batch.put(db::COL_HEADERS, &hash, &self.compress_block_data(header_bytes));
+        batch.put(db::COL_BODIES, &hash, &[]);
+
+        let details = BlockDetails {
+            number: header.number(),
+            total_difficulty: parent_details.total_difficulty + *header.difficulty(),
+            parent: parent_hash,
+            children: Vec::new(),
+        };
+        parent_details.children.push(hash);
+
+        let mut update = HashMap::new();
+        update.insert(parent_hash, parent_details);
+        update.insert(hash, details);
+
+        let mut write_details = self.block_details.write();
+        batch.extend_with_cache(
+            db::COL_EXTRA,
+            &mut *write_details,
+            update,
+            CacheUpdatePolicy::Overwrite,
+        );
+
+        let mut cache_man = self.cache_man.lock();
+        cache_man.note_used(CacheId::BlockDetails(parent_hash));
+        cache_man.note_used(CacheId::BlockDetails(hash));
+        Ok(())
+    }
+
+    /// Supply the body of a block stored with `insert_header`. The transactions of the body
+    /// must match the transactions root of the header.
+    pub fn attach_body(
+        &self,
+        batch: &mut DBTransaction,
+        hash: &H256,
+        body_bytes: &[u8],
+    ) -> Result<(), BlockImportError>
+    {
+        let header = self
+            .block_header_data(hash)
+            .ok_or_else(|| BlockImportError::Other(format!("Unknown header {}", hash)))?;
+        if self.block_body(hash).is_some() {
+            return Err(BlockImportError::Import(ImportError::AlreadyInChain));
+        }
+
+        let transactions = UntrustedRlp::new(body_bytes)
+            .at(0)
+            .map_err(|e| BlockImportError::Other(format!("Invalid body: {}", e)))?;
+        let found_root = ordered_trie_root(transactions.iter().map(|r| r.as_raw()));
+        if found_root != header.transactions_root() {
+            return Err(BlockImportError::Block(BlockError::InvalidTransactionsRoot(
+                Mismatch {
+                    expected: header.transactions_root(),
+                    found: found_root,
+                },
+            )));
+        }
+
+        batch.put(db::COL_BODIES, hash, &self.compress_block_data(body_bytes));
+        Ok(())
+    }
+
     /// Insert an epoch transition. Provide an epoch number being transitioned to
     /// and epoch transition object.
     ///
@@ -988,6 +1422,19 @@ impl BlockChain {
         }
     }
 
+    /// Iterate over canonical epoch transitions at block numbers in range `[from, to]`.
+    /// Transitions are ordered by block number, so iteration stops at the first one past `to`.
+    pub fn epoch_transitions_in<'a>(
+        &'a self,
+        from: u64,
+        to: u64,
+    ) -> impl Iterator<Item = (u64, EpochTransition)> + 'a
+    {
+        self.epoch_transitions()
+            .skip_while(move |(_, transition)| transition.block_number < from)
+            .take_while(move |(_, transition)| transition.block_number <= to)
+    }
+
     /// Get a specific epoch transition by block number and provided block hash.
     pub fn epoch_transition(&self, block_num: u64, block_hash: H256) -> Option<EpochTransition> {
         trace!(target: "blockchain", "Loading epoch transition at block {}, {}",
@@ -1054,6 +1501,12 @@ impl BlockChain {
         self.db.read(db::COL_EXTRA, &hash)
     }
 
+    /// Returns true if a pending epoch transition is stored for the given block hash,
+    /// without decoding it.
+    pub fn has_pending_transition(&self, hash: &H256) -> bool {
+        self.db.exists::<PendingEpochTransition, _>(db::COL_EXTRA, hash)
+    }
+
     /// Add a child to a given block. Assumes that the block hash is in
     /// the chain and the child's parent is this block.
     pub fn add_child(&self, batch: &mut DBTransaction, block_hash: H256, child_hash: H256) {
@@ -1079,6 +1532,73 @@ impl BlockChain {
             .note_used(CacheId::BlockDetails(block_hash));
     }
 
+    /// Recompute the children of a given block, dropping duplicated entries and entries
+    /// which are not known blocks at the next height with this block as parent.
+    /// The canonical block at the next height is added if it is missing.
+    pub fn repair_children(&self, batch: &mut DBTransaction, hash: &H256) {
+        let mut details = match self.block_details(hash) {
+            Some(details) => details,
+            None => return,
+        };
+
+        let child_number = details.number + 1;
+        let candidates = details
+            .children
+            .iter()
+            .cloned()
+            .chain(self.block_hash(child_number))
+            .collect::<Vec<_>>();
+
+        let mut children: Vec<H256> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if children.contains(&candidate) {
+                continue;
+            }
+            let is_child = self
+                .block_details(&candidate)
+                .map_or(false, |d| d.parent == *hash && d.number == child_number);
+            if is_child {
+                children.push(candidate);
+            }
+        }
+
+        if children == details.children {
+            return;
+        }
+        details.children = children;
+
+        let mut update = HashMap::new();
+        update.insert(*hash, details);
+
+        let mut write_details = self.block_details.write();
+        batch.extend_with_cache(
+            db::COL_EXTRA,
+            &mut *write_details,
+            update,
+            CacheUpdatePolicy::Overwrite,
+        );
+
+        self.cache_man
+            .lock()
+            .note_used(CacheId::BlockDetails(*hash));
+    }
+
+    /// Repair children of every canonical block in range `[from, to]`.
+    /// See `repair_children`.
+    pub fn repair_all_children(
+        &self,
+        batch: &mut DBTransaction,
+        from: BlockNumber,
+        to: BlockNumber,
+    )
+    {
+        for number in from..=to {
+            if let Some(hash) = self.block_hash(number) {
+                self.repair_children(batch, &hash);
+            }
+        }
+    }
+
     /// Inserts the block into backing cache database.
     /// Expects the block to be valid and already verified.
     /// If the block is already known, does nothing.
@@ -1088,20 +1608,82 @@ impl BlockChain {
         bytes: &[u8],
         receipts: Vec<Receipt>,
     ) -> ImportRoute
+    {
+        self.try_insert_block(batch, bytes, receipts)
+            .unwrap_or_else(|_| ImportRoute::none())
+    }
+
+    /// Like `insert_block`, but tells a block which was not imported apart from one which
+    /// was imported without changing the canonical chain: an already known block fails
+    /// with `ImportError::AlreadyInChain`. A block stored with `insert_header` is completed.
+    pub fn try_insert_block(
+        &self,
+        batch: &mut DBTransaction,
+        bytes: &[u8],
+        receipts: Vec<Receipt>,
+    ) -> Result<ImportRoute, BlockImportError>
     {
         // create views onto rlp
         let block = BlockView::new(bytes);
         let header = block.header_view();
         let hash = header.hash();
 
-        if self.is_known_child(&header.parent_hash(), &hash) {
-            return ImportRoute::none();
+        if self.is_known_child(&header.parent_hash(), &hash) && !self.is_header_only(&hash) {
+            return Err(BlockImportError::Import(ImportError::AlreadyInChain));
+        }
+
+        if self.verify_receipt_blooms {
+            if let Err(index) = Self::validate_receipt_blooms(&receipts) {
+                warn!(
+                    target: "blockchain",
+                    "Block #{} ({}) has invalid log bloom in receipt {}, ignoring it.",
+                    header.number(),
+                    hash,
+                    index
+                );
+                return Err(BlockImportError::Other(format!(
+                    "invalid log bloom in receipt {}",
+                    index
+                )));
+            }
+        }
+
+        if self.verify_receipts_root {
+            let found = receipts_root(&receipts);
+            if found != header.receipts_root() {
+                warn!(
+                    target: "blockchain",
+                    "Block #{} ({}) has mismatching receipts root, ignoring it.",
+                    header.number(),
+                    hash
+                );
+                return Err(BlockImportError::ReceiptsRootMismatch(Mismatch {
+                    expected: header.receipts_root(),
+                    found: found,
+                }));
+            }
+        }
+
+        if self.verify_transactions_root {
+            let found = transactions_root(&block.transactions());
+            if found != header.transactions_root() {
+                warn!(
+                    target: "blockchain",
+                    "Block #{} ({}) has mismatching transactions root, ignoring it.",
+                    header.number(),
+                    hash
+                );
+                return Err(BlockImportError::TransactionsRootMismatch(Mismatch {
+                    expected: header.transactions_root(),
+                    found: found,
+                }));
+            }
         }
 
         assert!(self.pending_best_block.read().is_none());
 
-        let compressed_header = compress(block.header_rlp().as_raw(), blocks_swapper());
-        let compressed_body = compress(&Self::block_to_body(bytes), blocks_swapper());
+        let compressed_header = self.compress_block_data(block.header_rlp().as_raw());
+        let compressed_body = self.compress_block_data(&Self::block_to_body(bytes));
 
         // store block in db
         batch.put(db::COL_HEADERS, &hash, &compressed_header);
@@ -1133,7 +1715,7 @@ impl BlockChain {
             true,
         );
 
-        ImportRoute::from(info)
+        Ok(ImportRoute::from(info))
     }
 
     /// Get inserted block info which is critical to prepare extras updates.
@@ -1165,23 +1747,10 @@ impl BlockChain {
                 match route.blocks.len() {
                     0 => BlockLocation::CanonChain,
                     _ => {
-                        let retracted = route
-                            .blocks
-                            .iter()
-                            .take(route.index)
-                            .cloned()
-                            .collect::<Vec<_>>()
-                            .into_iter()
-                            .collect::<Vec<_>>();
-                        let enacted = route
-                            .blocks
-                            .into_iter()
-                            .skip(route.index)
-                            .collect::<Vec<_>>();
                         BlockLocation::BranchBecomingCanonChain(BranchBecomingCanonChainData {
                             ancestor: route.ancestor,
-                            enacted: enacted,
-                            retracted: retracted,
+                            enacted: route.enacted().to_vec(),
+                            retracted: route.retracted().to_vec(),
                         })
                     }
                 }
@@ -1239,7 +1808,13 @@ impl BlockChain {
         {
             let mut best_block = self.pending_best_block.write();
             if is_best && update.info.location != BlockLocation::Branch {
-                batch.put(db::COL_EXTRA, b"best", &update.info.hash);
+                batch.put(db::COL_EXTRA, ExtraKey::Best.as_bytes(), &update.info.hash);
+                Self::write_best_block_hint(
+                    batch,
+                    update.info.hash,
+                    update.info.number,
+                    update.info.total_difficulty,
+                );
                 *best_block = Some(BestBlock {
                     hash: update.info.hash,
                     number: update.info.number,
@@ -1276,8 +1851,12 @@ impl BlockChain {
 
     /// Apply pending insertion updates
     pub fn commit(&self) {
+        let invalidated = mem::replace(&mut *self.pending_invalidations.write(), Vec::new());
+        self.invalidate(&invalidated);
+
         let mut pending_best_block = self.pending_best_block.write();
         let mut pending_write_hashes = self.pending_block_hashes.write();
+        let mut pending_removed_hashes = self.pending_removed_block_hashes.write();
         let mut pending_block_details = self.pending_block_details.write();
         let mut pending_write_txs = self.pending_transaction_addresses.write();
 
@@ -1300,6 +1879,9 @@ impl BlockChain {
         let pending_block_hashes: Vec<_> = pending_block_details.keys().cloned().collect();
 
         write_hashes.extend(mem::replace(&mut *pending_write_hashes, HashMap::new()));
+        for n in pending_removed_hashes.drain(..) {
+            write_hashes.remove(&n);
+        }
         write_txs.extend(
             enacted_txs
                 .into_iter()
@@ -1325,52 +1907,533 @@ impl BlockChain {
         }
     }
 
-    /// Iterator that lists `first` and then all of `first`'s ancestors, by hash.
-    pub fn ancestry_iter(&self, first: H256) -> Option<AncestryIter> {
-        if self.is_known(&first) {
-            Some(AncestryIter {
-                current: first,
-                chain: self,
+    /// Move the best block back to the canonical block at `number`.
+    ///
+    /// Blocks above `number` stop being canonical but stay in the database and can still be
+    /// queried by hash. Like `insert_block`, the in-memory best block and caches are only
+    /// updated by `commit`, which must be called after `batch` has been written.
+    pub fn rewind_to(&self, batch: &mut DBTransaction, number: BlockNumber) -> Result<(), Error> {
+        assert!(self.pending_best_block.read().is_none());
+
+        let first_number = self.first_block_number().unwrap_or(0);
+        let best_number = self.best_block_number();
+        if number < first_number || number > best_number {
+            return Err(BlockError::RidiculousNumber(OutOfBounds {
+                min: Some(first_number),
+                max: Some(best_number),
+                found: number,
             })
-        } else {
-            None
+            .into());
         }
-    }
 
-    /// This function returns modified block hashes.
-    fn prepare_block_hashes_update(
-        &self,
-        block_bytes: &[u8],
-        info: &BlockInfo,
-    ) -> HashMap<BlockNumber, H256>
-    {
-        let mut block_hashes = HashMap::new();
-        let block = BlockView::new(block_bytes);
-        let header = block.header_view();
-        let number = header.number();
-
-        match info.location {
-            BlockLocation::Branch => (),
-            BlockLocation::CanonChain => {
-                block_hashes.insert(number, info.hash);
-            }
-            BlockLocation::BranchBecomingCanonChain(ref data) => {
-                let ancestor_number = self
-                    .block_number(&data.ancestor)
-                    .expect("Block number of ancestor is always in DB");
-                let start_number = ancestor_number + 1;
+        let hash = self
+            .block_hash(number)
+            .expect("canonical block within the best part of the chain must exist; qed");
+        let details = self
+            .block_details(&hash)
+            .expect("canonical block details must exist; qed");
+        let block = self
+            .block(&hash)
+            .expect("canonical block must exist; qed")
+            .into_inner();
+        let timestamp = BlockView::new(&block).header_view().timestamp();
+
+        let rewound_transactions = (number + 1..=best_number)
+            .filter_map(|n| self.block_hash(n))
+            .filter_map(|h| self.block_body(&h))
+            .flat_map(|body| body.transaction_hashes())
+            .collect::<Vec<_>>();
 
-                for (index, hash) in data.enacted.iter().cloned().enumerate() {
-                    block_hashes.insert(start_number + index as BlockNumber, hash);
-                }
+        let mut pending_best_block = self.pending_best_block.write();
+        let mut pending_removed_hashes = self.pending_removed_block_hashes.write();
+        let mut pending_txs = self.pending_transaction_addresses.write();
 
-                block_hashes.insert(number, info.hash);
-            }
+        for n in number + 1..=best_number {
+            Writable::delete::<H256, _>(batch, db::COL_EXTRA, &n);
+            pending_removed_hashes.push(n);
         }
+        for tx_hash in rewound_transactions {
+            Writable::delete::<TransactionAddress, _>(batch, db::COL_EXTRA, &tx_hash);
+            pending_txs.insert(tx_hash, None);
+        }
+        batch.put(db::COL_EXTRA, ExtraKey::Best.as_bytes(), &hash);
+        Self::write_best_block_hint(batch, hash, number, details.total_difficulty);
+
+        *pending_best_block = Some(BestBlock {
+            hash: hash,
+            number: number,
+            total_difficulty: details.total_difficulty,
+            timestamp: timestamp,
+            block: block,
+        });
+        Ok(())
+    }
+
+    /// Delete the receipts of canonical blocks below block `number`. Refuses to prune, removing
+    /// nothing, if that would reach into the configured number of most recent blocks whose
+    /// receipts are retained. Returns the number of receipts removed.
+    ///
+    /// The receipts are dropped from the cache by `commit`, which must be called after `batch`
+    /// has been written.
+    pub fn prune_receipts_below(&self, batch: &mut DBTransaction, number: BlockNumber) -> usize {
+        let retained_from = self
+            .best_block_number()
+            .saturating_sub(self.receipts_retained_depth);
+        if number > retained_from {
+            warn!(
+                target: "blockchain",
+                "Refusing to prune receipts below #{}, receipts from #{} are retained.",
+                number,
+                retained_from
+            );
+            return 0;
+        }
+
+        let mut pruned = Vec::new();
+        for n in 0..number {
+            let hash = match self.block_hash(n) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            if self
+                .db
+                .exists_with_cache(db::COL_EXTRA, &self.block_receipts, &hash)
+            {
+                Writable::delete::<BlockReceipts, _>(batch, db::COL_EXTRA, &hash);
+                pruned.push(CacheId::BlockReceipts(hash));
+            }
+        }
+        let count = pruned.len();
+        self.pending_invalidations.write().extend(pruned);
+        count
+    }
+
+    /// Read a value stored in the extras column under a custom `key`, without decoding it.
+    pub fn get_raw_extra(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db
+            .get(db::COL_EXTRA, key)
+            .expect("EXTRA db not be found")
+            .map(|value| value.to_vec())
+    }
+
+    /// Store `value` in the extras column under a custom `key`, for data kept alongside the
+    /// chain by tooling and plugins.
+    ///
+    /// The key must not collide with anything the chain stores itself, so keys starting with
+    /// an `ExtrasIndex` byte or a reserved `ExtraKey` are rejected without touching `batch`.
+    pub fn put_raw_extra(
+        &self,
+        batch: &mut DBTransaction,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error>
+    {
+        if ExtraKey::is_reserved(key) {
+            return Err(Error::Database(DbError::Other(format!(
+                "extras key {:?} is reserved",
+                key
+            ))));
+        }
+        batch.put(db::COL_EXTRA, key, value);
+        Ok(())
+    }
+
+    /// Delete side branches forking off the canonical chain which lie entirely below
+    /// block `below`: their headers, bodies, details and receipts. Branches reaching
+    /// `below` are kept whole, and canonical blocks are never removed.
+    /// Returns the number of blocks removed. Caches are updated by `commit`, which must be
+    /// called after `batch` has been written.
+    pub fn prune_orphans(&self, batch: &mut DBTransaction, below: BlockNumber) -> usize {
+        let mut removed = 0;
+        let end = ::std::cmp::min(below, self.best_block_number() + 1);
+        for number in 0..end {
+            let hash = match self.block_hash(number) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let mut details = match self.block_details(&hash) {
+                Some(details) => details,
+                None => continue,
+            };
+
+            let children = mem::replace(&mut details.children, Vec::new());
+            let mut kept = Vec::with_capacity(children.len());
+            for child in children.iter().cloned() {
+                if self.contains_canonical(&child) {
+                    kept.push(child);
+                    continue;
+                }
+                let branch = self.side_branch(child);
+                if branch.iter().any(|&(_, n)| n >= below) {
+                    kept.push(child);
+                    continue;
+                }
+                for (orphan, _) in branch {
+                    batch.delete(db::COL_HEADERS, &orphan);
+                    batch.delete(db::COL_BODIES, &orphan);
+                    Writable::delete::<BlockDetails, _>(batch, db::COL_EXTRA, &orphan);
+                    Writable::delete::<BlockReceipts, _>(batch, db::COL_EXTRA, &orphan);
+                    self.pending_invalidations.write().extend(vec![
+                        CacheId::BlockHeader(orphan),
+                        CacheId::BlockBody(orphan),
+                        CacheId::BlockDetails(orphan),
+                        CacheId::BlockReceipts(orphan),
+                    ]);
+                    removed += 1;
+                }
+            }
+            if kept.len() == children.len() {
+                continue;
+            }
+
+            details.children = kept;
+            let mut update = HashMap::new();
+            update.insert(hash, details);
+            let mut write_details = self.pending_block_details.write();
+            batch.extend_with_cache(
+                db::COL_EXTRA,
+                &mut *write_details,
+                update,
+                CacheUpdatePolicy::Overwrite,
+            );
+        }
+        removed
+    }
+
+    // `root` and all of its descendants, with their numbers.
+    fn side_branch(&self, root: H256) -> Vec<(H256, BlockNumber)> {
+        let mut branch = Vec::new();
+        let mut pending = vec![root];
+        while let Some(hash) = pending.pop() {
+            if let Some(details) = self.block_details(&hash) {
+                pending.extend(details.children.iter().cloned());
+                branch.push((hash, details.number));
+            }
+        }
+        branch
+    }
+
+    /// Iterator that lists `first` and then all of `first`'s ancestors, by hash.
+    pub fn ancestry_iter(&self, first: H256) -> Option<AncestryIter> {
+        if self.is_known(&first) {
+            Some(AncestryIter {
+                current: first,
+                chain: self,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the first block of the canonical chain among `hash` and its ancestors,
+    /// i.e. `hash` itself if it's canonical or the point its side branch forks off.
+    /// `None` if the walk leaves the known chain first.
+    pub fn nearest_canonical_ancestor(&self, hash: H256) -> Option<H256> {
+        self.ancestry_iter(hash)?
+            .find(|ancestor| self.contains_canonical(ancestor))
+    }
+
+    /// Recompute the log bloom of each receipt and compare it with the stored one.
+    /// Returns the index of the first receipt whose bloom doesn't match its logs.
+    pub fn validate_receipt_blooms(receipts: &[Receipt]) -> Result<(), usize> {
+        let mismatch = receipts
+            .iter()
+            .position(|receipt| &bloom_for_logs(receipt.logs()) != receipt.log_bloom());
+        match mismatch {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if `maybe_ancestor` is `descendant` itself or one of its ancestors.
+    /// Unknown blocks are never part of any ancestry.
+    pub fn ancestry_contains(&self, descendant: H256, maybe_ancestor: H256) -> bool {
+        let ancestor_number = match self.block_number(&maybe_ancestor) {
+            Some(number) => number,
+            None => return false,
+        };
+
+        let mut current = descendant;
+        loop {
+            if current == maybe_ancestor {
+                return true;
+            }
+            match self.block_details(&current) {
+                Some(ref details) if details.number > ancestor_number => {
+                    current = details.parent;
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /// Lazily iterate over canonical blocks newest-first, from the best block down to genesis.
+    /// Parents are followed through block details, so numbers are never resolved to hashes.
+    pub fn canonical_reverse_iter<'a>(&'a self) -> impl Iterator<Item = (BlockNumber, H256)> + 'a {
+        let (best_number, best_hash) = {
+            let best_block = self.best_block.read();
+            (best_block.number, best_block.hash)
+        };
+        self.ancestry_iter(best_hash)
+            .into_iter()
+            .flat_map(|ancestry| ancestry)
+            .zip((0..=best_number).rev())
+            .map(|(hash, number)| (number, hash))
+    }
+
+    /// Lazily iterate over receipts of canonical blocks in range `[from, to]`,
+    /// ordered by block number. Numbers without a known hash or receipts are skipped.
+    pub fn receipts_in_range<'a>(
+        &'a self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> impl Iterator<Item = (BlockNumber, H256, BlockReceipts)> + 'a
+    {
+        (from..=to).filter_map(move |number| {
+            self.block_hash(number).and_then(|hash| {
+                self.block_receipts(&hash)
+                    .map(|receipts| (number, hash, receipts))
+            })
+        })
+    }
+
+    /// Hashes of the canonical blocks in range `[from, to]` sealed by `author`,
+    /// ordered by block number. Numbers without a known hash are skipped.
+    pub fn blocks_by_author(
+        &self,
+        author: Address,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<H256>
+    {
+        (from..=to)
+            .filter_map(|number| self.block_hash(number))
+            .filter(|hash| {
+                self.block_header_data(hash)
+                    .map_or(false, |header| header.author() == author)
+            })
+            .collect()
+    }
+
+    /// Sample canonical blocks in range `[from, to]` every `step` blocks, returning their
+    /// number, hash, state root and total difficulty. Both ends of the range are included,
+    /// and numbers without a known block are skipped.
+    pub fn export_state_roots(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+        step: u64,
+    ) -> Vec<(BlockNumber, H256, H256, U256)>
+    {
+        assert!(step > 0, "state root sampling step must be positive");
+        let mut numbers = Vec::new();
+        let mut number = from;
+        while number < to {
+            numbers.push(number);
+            number = number.saturating_add(step);
+        }
+        if from <= to {
+            numbers.push(to);
+        }
+
+        numbers
+            .into_iter()
+            .filter_map(|number| {
+                let hash = self.block_hash(number)?;
+                let header = self.block_header_data(&hash)?;
+                let details = self.block_details(&hash)?;
+                Some((number, hash, header.state_root(), details.total_difficulty))
+            })
+            .collect()
+    }
+
+    /// Returns up to `n` most recent transactions of the canonical chain, newest first.
+    /// Stops walking back the chain as soon as `n` transactions are collected.
+    pub fn latest_transactions(&self, n: usize) -> Vec<LocalizedTransaction> {
+        let mut result = Vec::with_capacity(n);
+        let mut number = self.best_block_number();
+        while result.len() < n {
+            let transactions = self
+                .block_hash(number)
+                .and_then(|hash| self.transactions(&hash));
+            if let Some(transactions) = transactions {
+                let needed = n - result.len();
+                result.extend(transactions.into_iter().rev().take(needed));
+            }
+            if number == 0 {
+                break;
+            }
+            number -= 1;
+        }
+        result
+    }
+
+    /// Split the missing parts of the canonical chain into ranges of at most `chunk` blocks,
+    /// suitable for handing out to sync workers. Ranges are inclusive and ordered with the
+    /// highest block numbers first.
+    pub fn gap_work_items(&self, chunk: u64) -> Vec<(BlockNumber, BlockNumber)> {
+        assert!(chunk > 0, "work item chunk size must be positive");
+        let mut items = Vec::new();
+        for (start, end) in self.find_gaps().into_iter().rev() {
+            let mut to = end;
+            loop {
+                let from = if to - start + 1 > chunk {
+                    to + 1 - chunk
+                } else {
+                    start
+                };
+                items.push((from, to));
+                if from == start {
+                    break;
+                }
+                to = from - 1;
+            }
+        }
+        items
+    }
+
+    /// Inclusive ranges of block numbers below the best block that have no canonical hash,
+    /// in ascending order.
+    fn find_gaps(&self) -> Vec<(BlockNumber, BlockNumber)> {
+        let mut gaps = Vec::new();
+        let mut gap_start = None;
+        let best = self.best_block_number();
+        for number in 0..best {
+            match (self.block_hash(number).is_some(), gap_start) {
+                (false, None) => gap_start = Some(number),
+                (true, Some(start)) => {
+                    gaps.push((start, number - 1));
+                    gap_start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(start) = gap_start {
+            gaps.push((start, best - 1));
+        }
+        gaps
+    }
+
+    /// Write the canonical blocks `from..=to` as a block dump of this chain.
+    pub fn export_blocks<W: Write>(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+        out: W,
+    ) -> io::Result<W>
+    {
+        let mut writer = ExportWriter::new(out, &self.genesis_hash())?;
+        for number in from..(to + 1) {
+            let block = self
+                .block_hash(number)
+                .and_then(|hash| self.block(&hash))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Block #{} is not in the chain", number),
+                    )
+                })?;
+            writer.write_block(&block.into_inner())?;
+        }
+        writer.finish()
+    }
+
+    /// Check a block dump, as written by the blockchain export, before importing it.
+    ///
+    /// The dump must come from a chain with the same genesis and match its checksum.
+    /// Every block must decode and be the child of the previous one, and the first block
+    /// must attach to this chain. Nothing is written to the database.
+    pub fn verify_export<R: Read>(&self, input: &mut R) -> Result<ExportSummary, ExportError> {
+        let mut reader = ExportReader::new(input, &self.genesis_hash())?;
+        let mut summary: Option<ExportSummary> = None;
+        let mut parent: Option<H256> = None;
+        let mut index = 0;
+
+        while let Some(bytes) = reader.next_block()? {
+            let header = UntrustedRlp::new(&bytes)
+                .as_val::<::block::Block>()
+                .map_err(|e| ExportError::Decode(index, e))?
+                .header;
+
+            match parent {
+                Some(expected) => {
+                    if *header.parent_hash() != expected {
+                        return Err(ExportError::Discontinuity {
+                            index: index,
+                            expected: expected,
+                            found: *header.parent_hash(),
+                        });
+                    }
+                }
+                None => {
+                    let attached = if header.number() == 0 {
+                        header.hash() == self.genesis_hash()
+                    } else {
+                        self.is_known(header.parent_hash())
+                    };
+                    if !attached {
+                        return Err(ExportError::UnknownParent(*header.parent_hash()));
+                    }
+                }
+            }
+
+            summary = Some(match summary {
+                Some(s) => {
+                    ExportSummary {
+                        last: header.number(),
+                        count: s.count + 1,
+                        ..s
+                    }
+                }
+                None => {
+                    ExportSummary {
+                        first: header.number(),
+                        last: header.number(),
+                        count: 1,
+                    }
+                }
+            });
+            parent = Some(header.hash());
+            index += 1;
+        }
+
+        summary.ok_or(ExportError::Empty)
+    }
+
+    /// This function returns modified block hashes.
+    fn prepare_block_hashes_update(
+        &self,
+        block_bytes: &[u8],
+        info: &BlockInfo,
+    ) -> HashMap<BlockNumber, H256>
+    {
+        let mut block_hashes = HashMap::new();
+        let block = BlockView::new(block_bytes);
+        let header = block.header_view();
+        let number = header.number();
+
+        match info.location {
+            BlockLocation::Branch => (),
+            BlockLocation::CanonChain => {
+                block_hashes.insert(number, info.hash);
+            }
+            BlockLocation::BranchBecomingCanonChain(ref data) => {
+                let ancestor_number = self
+                    .block_number(&data.ancestor)
+                    .expect("Block number of ancestor is always in DB");
+                let start_number = ancestor_number + 1;
+
+                for (index, hash) in data.enacted.iter().cloned().enumerate() {
+                    block_hashes.insert(start_number + index as BlockNumber, hash);
+                }
+
+                block_hashes.insert(number, info.hash);
+            }
+        }
+
+        block_hashes
+    }
 
-        block_hashes
-    }
-
     /// This function returns modified block details.
     /// Uses the given parent details or attempts to load them from the database.
     fn prepare_block_details_update(
@@ -1385,14 +2448,21 @@ impl BlockChain {
         let mut parent_details = self
             .block_details(&parent_hash)
             .unwrap_or_else(|| panic!("Invalid parent hash: {:?}", parent_hash));
-        parent_details.children.push(info.hash);
+        // a block stored with `insert_header` is already a child and may have children itself
+        let children = if parent_details.children.contains(&info.hash) {
+            self.block_details(&info.hash)
+                .map_or_else(Vec::new, |details| details.children)
+        } else {
+            parent_details.children.push(info.hash);
+            vec![]
+        };
 
         // create current block details.
         let details = BlockDetails {
             number: header.number(),
             total_difficulty: info.total_difficulty,
             parent: parent_hash,
-            children: vec![],
+            children: children,
         };
 
         // write to batch
@@ -1585,6 +2655,84 @@ impl BlockChain {
         encoded::Header::new(raw)
     }
 
+    /// Get the timestamp of the block with given hash, without decoding the rest of its header.
+    pub fn block_timestamp(&self, hash: &H256) -> Option<u64> {
+        if self.best_block_fast_path {
+            let best_block = self.best_block.read();
+            if &best_block.hash == hash {
+                return Some(best_block.timestamp);
+            }
+        }
+        self.block_header_data(hash).map(|header| header.timestamp())
+    }
+
+    /// Get the timestamp of the canonical block with given number.
+    pub fn block_timestamp_at(&self, number: BlockNumber) -> Option<u64> {
+        self.block_hash(number).and_then(|hash| self.block_timestamp(&hash))
+    }
+
+    /// Get the RLP of the block with given hash, assembled from the cached header and body.
+    /// The best block is served as is from memory.
+    pub fn block_rlp(&self, hash: &H256) -> Option<Bytes> {
+        if self.best_block_fast_path {
+            let best_block = self.best_block.read();
+            if &best_block.hash == hash {
+                return Some(best_block.block.clone());
+            }
+        }
+
+        match (self.block_header_data(hash), self.block_body(hash)) {
+            (Some(header), Some(body)) => {
+                let mut block = RlpStream::new_list(2);
+                let body_rlp = body.rlp();
+                block.append_raw(header.rlp().as_raw(), 1);
+                block.append_raw(body_rlp.at(0).as_raw(), 1);
+                Some(block.out())
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the author of the block with given hash, without decoding the rest of its header.
+    pub fn block_author(&self, hash: &H256) -> Option<Address> {
+        self.block_header_data(hash).map(|header| header.author())
+    }
+
+    /// Get the difficulty of the block with given hash, without decoding the rest of its header.
+    pub fn block_difficulty(&self, hash: &H256) -> Option<U256> {
+        self.block_header_data(hash).map(|header| header.difficulty())
+    }
+
+    /// Get the difficulty of the canonical block with given number.
+    pub fn difficulty_at(&self, number: BlockNumber) -> Option<U256> {
+        self.block_hash(number).and_then(|hash| self.block_difficulty(&hash))
+    }
+
+    /// Returns the number of the highest canonical block at or above `from` containing given
+    /// bloom. The bloom chain is queried from the best block downwards in growing windows, so
+    /// a recent match doesn't require scanning the whole range.
+    pub fn latest_block_with_bloom(
+        &self,
+        bloom: &Bloom,
+        from: BlockNumber,
+    ) -> Option<BlockNumber>
+    {
+        let mut to = self.best_block_number();
+        let mut window = LOG_BLOOMS_ELEMENTS_PER_INDEX as BlockNumber;
+        while from <= to {
+            let start = ::std::cmp::max(from, to.saturating_sub(window - 1));
+            if let Some(number) = self.blocks_with_bloom(bloom, start, to).into_iter().max() {
+                return Some(number);
+            }
+            if start == 0 {
+                break;
+            }
+            to = start - 1;
+            window = window.saturating_mul(2);
+        }
+        None
+    }
+
     /// Get current cache size.
     pub fn cache_size(&self) -> CacheSize {
         CacheSize {
@@ -1597,6 +2745,98 @@ impl BlockChain {
         }
     }
 
+    /// Keys currently held by each cache, for debugging cache coherence.
+    /// Every cache is locked only for the time needed to copy its keys.
+    #[cfg(any(test, feature = "debug-cache"))]
+    pub fn cache_keys(&self) -> CacheKeys {
+        CacheKeys {
+            block_headers: self.block_headers.read().keys().cloned().collect(),
+            block_bodies: self.block_bodies.read().keys().cloned().collect(),
+            block_details: self.block_details.read().keys().cloned().collect(),
+            block_hashes: self.block_hashes.read().keys().cloned().collect(),
+            transaction_addresses: self.transaction_addresses.read().keys().cloned().collect(),
+            blocks_blooms: self.blocks_blooms.read().len(),
+            block_receipts: self.block_receipts.read().keys().cloned().collect(),
+        }
+    }
+
+    /// Load headers, bodies and details of canonical blocks in range `[from, to]` into the
+    /// caches. Numbers without a canonical block, e.g. in a gap, are skipped. Stops early once
+    /// the caches reach the configured maximum size.
+    pub fn warm_cache(&self, from: BlockNumber, to: BlockNumber) {
+        let max_cache_size = self.cache_man.lock().max_cache_size();
+        let to = ::std::cmp::min(to, self.best_block_number());
+        // measuring the caches walks every entry, so measure once and add up what is loaded.
+        // Blocks that were already cached are counted again, which only stops warming earlier.
+        let mut cache_size = self.cache_size().total();
+        for number in from..=to {
+            if cache_size >= max_cache_size {
+                debug!(target: "blockchain", "Cache full, stopped warming at block {}", number);
+                break;
+            }
+            let hash = match self.block_hash(number) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            cache_size += self.block_header_data(&hash).heap_size_of_children();
+            cache_size += self.block_body(&hash).heap_size_of_children();
+            cache_size += self.block_details(&hash).heap_size_of_children();
+        }
+    }
+
+    /// Drop every cached entry keyed by the given block hash, so that the next read goes
+    /// to the database. Used after surgical database changes such as a rewind or a repair.
+    pub fn invalidate_block(&self, hash: &H256) {
+        self.invalidate(&[
+            CacheId::BlockHeader(*hash),
+            CacheId::BlockBody(*hash),
+            CacheId::BlockDetails(*hash),
+            CacheId::BlockReceipts(*hash),
+        ]);
+    }
+
+    /// Drop the cached canonical hashes of the given block numbers.
+    pub fn invalidate_block_hashes(&self, numbers: &[BlockNumber]) {
+        let ids: Vec<_> = numbers.iter().map(|n| CacheId::BlockHashes(*n)).collect();
+        self.invalidate(&ids);
+    }
+
+    fn invalidate(&self, ids: &[CacheId]) {
+        let mut block_headers = self.block_headers.write();
+        let mut block_bodies = self.block_bodies.write();
+        let mut block_details = self.block_details.write();
+        let mut block_hashes = self.block_hashes.write();
+        let mut transaction_addresses = self.transaction_addresses.write();
+        let mut blocks_blooms = self.blocks_blooms.write();
+        let mut block_receipts = self.block_receipts.write();
+
+        for id in ids {
+            match *id {
+                CacheId::BlockHeader(ref h) => {
+                    block_headers.remove(h);
+                }
+                CacheId::BlockBody(ref h) => {
+                    block_bodies.remove(h);
+                }
+                CacheId::BlockDetails(ref h) => {
+                    block_details.remove(h);
+                }
+                CacheId::BlockHashes(ref h) => {
+                    block_hashes.remove(h);
+                }
+                CacheId::TransactionAddresses(ref h) => {
+                    transaction_addresses.remove(h);
+                }
+                CacheId::BlocksBlooms(ref h) => {
+                    blocks_blooms.remove(h);
+                }
+                CacheId::BlockReceipts(ref h) => {
+                    block_receipts.remove(h);
+                }
+            }
+        }
+    }
+
     /// Ticks our cache system and throws out any old data.
     pub fn collect_garbage(&self) {
         let current_size = self.cache_size().total();
@@ -1655,12 +2895,51 @@ impl BlockChain {
         });
     }
 
-    /// Create a block body from a block.
-    pub fn block_to_body(block: &[u8]) -> Bytes {
-        let mut body = RlpStream::new_list(1);
-        let block_rlp = Rlp::new(block);
-        body.append_raw(block_rlp.at(1).as_raw(), 1);
-        body.out()
+    /// Prepare header or body rlp for storage according to the configured compression.
+    fn compress_block_data(&self, rlp: &[u8]) -> Bytes {
+        match (self.compression, self.swapper.as_ref()) {
+            (CompressionMode::Blocks, Some(swapper)) => compress(rlp, swapper).into_vec(),
+            (CompressionMode::Blocks, None) => compress(rlp, blocks_swapper()).into_vec(),
+            (CompressionMode::None, _) => rlp.to_vec(),
+        }
+    }
+
+    /// Restore header or body rlp read from storage with the configured dictionary.
+    fn decompress_block_data(&self, stored: &[u8]) -> Bytes {
+        match self.swapper {
+            Some(ref swapper) => decompress(stored, swapper).into_vec(),
+            None => decompress(stored, blocks_swapper()).into_vec(),
+        }
+    }
+
+    /// Fingerprint of a compression dictionary, persisted to detect reopening the database
+    /// with another dictionary.
+    fn dictionary_fingerprint(dictionary: &[Bytes]) -> H256 {
+        let mut stream = RlpStream::new_list(dictionary.len());
+        for rlp in dictionary {
+            stream.append(rlp);
+        }
+        blake2b::blake2b(&stream.out())
+    }
+
+    /// Fingerprint of the compression dictionary the database was created with, `None` for
+    /// the built-in one.
+    fn stored_dictionary_fingerprint(db: &KeyValueDB) -> Option<H256> {
+        db.get(db::COL_EXTRA, ExtraKey::Dictionary.as_bytes())
+            .expect("EXTRA db not be found")
+            .map(|fingerprint| H256::from_slice(&fingerprint))
+    }
+
+    /// Whether the database was created with another compression dictionary than the
+    /// configured one. Blocks stored before may not be read back correctly.
+    pub fn has_dictionary_mismatch(&self) -> bool { self.dictionary_mismatch }
+
+    /// Create a block body from a block.
+    pub fn block_to_body(block: &[u8]) -> Bytes {
+        let mut body = RlpStream::new_list(1);
+        let block_rlp = Rlp::new(block);
+        body.append_raw(block_rlp.at(1).as_raw(), 1);
+        body.out()
     }
 
     /// Returns general blockchain information
@@ -1687,20 +2966,27 @@ impl BlockChain {
 mod tests {
     use std::iter;
     use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
     use rustc_hex::FromHex;
-    use kvdb::{KeyValueDB, MockDbRepository, DBTransaction};
+    use kvdb::{KeyValueDB, MockDbRepository, DBTransaction, DBValue};
     use aion_types::*;
     use ethbloom::Bloom;
     use receipt::{Receipt, SimpleReceipt};
-    use blockchain::{BlockProvider, BlockChain, Config, ImportRoute};
+    use rlp_compress::{decompress, DictionarySwapper};
+    use blockchain::{
+        bloom_for_logs, receipts_root, transactions_root, BlockProvider, BlockChain, Config,
+        CompressionMode, ImportRoute, ExportSummary, ExportError, ExportWriter, ExtraKey,
+    };
     use tests::helpers::*;
     use blockchain::generator::{BlockGenerator, BlockBuilder, BlockOptions};
-    use blockchain::extras::TransactionAddress;
+    use blockchain::extras::{BestBlockHint, ExtrasIndex, TransactionAddress};
     use transaction::{Transaction, Action, DEFAULT_TRANSACTION_TYPE};
     use log_entry::{LogEntry, LocalizedLogEntry};
     use bytes::Bytes;
     use keychain;
-    use db;
+    use db::{self, Readable, Writable};
+    use error::{Error, BlockError, BlockImportError, ImportError};
+    use rayon::{Configuration, ThreadPool};
 
     fn new_db() -> Arc<KeyValueDB> {
         let mut db_configs = Vec::new();
@@ -1805,116 +3091,1463 @@ mod tests {
     }
 
     #[test]
-    fn test_fork_transaction_addresses() {
-        let t1 = Transaction {
-            nonce: 0.into(),
-            gas_price: 0.into(),
-            gas: 100_000.into(),
-            action: Action::Create,
-            value: 100.into(),
-            data: "601080600c6000396000f3006000355415600957005b60203560003555"
-                .from_hex()
-                .unwrap(),
-            transaction_type: DEFAULT_TRANSACTION_TYPE,
-            nonce_bytes: Vec::new(),
-            gas_bytes: Vec::new(),
-            gas_price_bytes: Vec::new(),
-            value_bytes: Vec::new(),
+    fn check_canonical_reverse_iter() {
+        let genesis = BlockBuilder::genesis();
+        let first_5 = genesis.add_blocks(5);
+        let side = genesis.add_blocks_with(3, || {
+            BlockOptions {
+                difficulty: 9.into(),
+                ..Default::default()
+            }
+        });
+        let generator = BlockGenerator::new(vec![first_5, side]);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in generator {
+            insert_block(&db, &bc, &block.encoded(), vec![]);
         }
-        .sign(keychain::ethkey::generate_keypair().secret(), None);
 
-        let t1_hash = t1.hash();
+        let mut forward = (0..=bc.best_block_number())
+            .map(|number| (number, bc.block_hash(number).unwrap()))
+            .collect::<Vec<_>>();
+        forward.reverse();
+        assert_eq!(bc.canonical_reverse_iter().collect::<Vec<_>>(), forward);
+        assert_eq!(forward.len(), 6);
+        assert_eq!(
+            bc.canonical_reverse_iter().next(),
+            Some((5, bc.best_block_hash()))
+        );
+        assert_eq!(
+            bc.canonical_reverse_iter().last(),
+            Some((0, genesis.last().hash()))
+        );
+    }
 
+    #[test]
+    fn check_ancestry_contains() {
         let genesis = BlockBuilder::genesis();
-        let b1a = genesis.add_block_with_transactions(iter::once(t1));
-        let b1b = genesis.add_block_with_difficulty(9);
-        let b2 = b1b.add_block();
-
-        let b1a_hash = b1a.last().hash();
-        let b2_hash = b2.last().hash();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3a = b2.add_block();
+        let b3b = b2.add_block_with_difficulty(9);
+        let b4b = b3b.add_block();
 
         let db = new_db();
         let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3a, &b3b, &b4b] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
 
-        let mut batch = DBTransaction::new();
-        let _ = bc.insert_block(&mut batch, &b1a.last().encoded(), vec![]);
-        bc.commit();
-        let _ = bc.insert_block(&mut batch, &b1b.last().encoded(), vec![]);
-        bc.commit();
-        db.write(batch).unwrap();
+        let genesis_hash = genesis.last().hash();
+        let b2_hash = b2.last().hash();
+        let b3a_hash = b3a.last().hash();
+        let b3b_hash = b3b.last().hash();
+        let b4b_hash = b4b.last().hash();
+
+        assert!(bc.ancestry_contains(b4b_hash, b3b_hash));
+        assert!(bc.ancestry_contains(b4b_hash, b2_hash));
+        assert!(bc.ancestry_contains(b4b_hash, genesis_hash));
+        assert!(bc.ancestry_contains(b3a_hash, b2_hash));
+        assert!(bc.ancestry_contains(b3a_hash, b3a_hash));
+        // b3a is on the other branch
+        assert!(!bc.ancestry_contains(b4b_hash, b3a_hash));
+        assert!(!bc.ancestry_contains(b3a_hash, b3b_hash));
+        // descendants are not ancestors
+        assert!(!bc.ancestry_contains(b2_hash, b4b_hash));
+        // unknown blocks
+        assert!(!bc.ancestry_contains(H256::from(1), b2_hash));
+        assert!(!bc.ancestry_contains(b4b_hash, H256::from(1)));
+    }
 
-        assert_eq!(bc.best_block_hash(), b1a_hash);
-        assert_eq!(
-            bc.transaction_address(&t1_hash),
-            Some(TransactionAddress {
-                block_hash: b1a_hash,
-                index: 0,
-            })
+    #[test]
+    fn check_bloom_for_logs() {
+        assert_eq!(bloom_for_logs(&[]), Bloom::default());
+
+        let logs = vec![
+            LogEntry {
+                address: Address::from(1),
+                topics: vec![H256::from(2), H256::from(3)],
+                data: vec![],
+            },
+            LogEntry {
+                address: Address::from(4),
+                topics: vec![],
+                data: vec![5],
+            },
+        ];
+        let receipt = Receipt::new(
+            H256::default(),
+            U256::from(21_000),
+            U256::from(21_000),
+            logs.clone(),
+            Bytes::default(),
+            String::default(),
         );
+        assert_eq!(&bloom_for_logs(&logs), receipt.log_bloom());
+        assert!(bloom_for_logs(&logs).contains_bloom(&logs[1].bloom()));
+    }
 
-        // now let's make forked chain the canon chain
+    #[test]
+    fn check_receipt_blooms() {
+        let receipt = |data: u8| {
+            Receipt::new(
+                H256::default(),
+                10_000.into(),
+                U256::zero(),
+                vec![LogEntry {
+                    address: Address::from(data as u64),
+                    topics: vec![H256::from(data as u64)],
+                    data: vec![data],
+                }],
+                Bytes::default(),
+                String::default(),
+            )
+        };
+        let mut receipts = vec![receipt(1), receipt(2), receipt(3)];
+        assert_eq!(BlockChain::validate_receipt_blooms(&receipts), Ok(()));
+
+        receipts[1].simple_receipt.log_bloom = receipts[0].log_bloom().clone();
+        assert_eq!(BlockChain::validate_receipt_blooms(&receipts), Err(1));
+
+        // blocks with tampered receipts are not inserted when verification is enabled
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let db = new_db();
+        let config = Config {
+            verify_receipt_blooms: true,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let route = insert_block(&db, &bc, &b1.last().encoded(), receipts);
+        assert_eq!(route, ImportRoute::none());
+        assert!(!bc.is_known(&b1.last().hash()));
+    }
+
+    #[test]
+    fn check_verify_receipts_root() {
+        let receipt = Receipt::new(
+            H256::default(),
+            10_000.into(),
+            U256::zero(),
+            vec![],
+            Bytes::default(),
+            String::default(),
+        );
+        assert_ne!(receipts_root(&[receipt.clone()]), receipts_root(&[]));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let db = new_db();
+        let config = Config {
+            verify_receipts_root: true,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+
+        // generated blocks commit to an empty receipts trie
         let mut batch = DBTransaction::new();
-        let _ = bc.insert_block(&mut batch, &b2.last().encoded(), vec![]);
-        bc.commit();
+        let res = bc.try_insert_block(&mut batch, &b1.last().encoded(), vec![]);
         db.write(batch).unwrap();
+        bc.commit();
+        assert!(res.is_ok());
+        assert!(bc.is_known(&b1.last().hash()));
 
-        // Transaction should be retracted
-        assert_eq!(bc.best_block_hash(), b2_hash);
-        assert_eq!(bc.transaction_address(&t1_hash), None);
+        let mut batch = DBTransaction::new();
+        match bc.try_insert_block(&mut batch, &b2.last().encoded(), vec![receipt.clone()]) {
+            Err(BlockImportError::ReceiptsRootMismatch(mismatch)) => {
+                assert_eq!(mismatch.expected, b2.last().header().receipts_root().clone());
+                assert_eq!(mismatch.found, receipts_root(&[receipt]));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        db.write(batch).unwrap();
+        bc.commit();
+        assert!(!bc.is_known(&b2.last().hash()));
     }
 
     #[test]
-    fn test_overwriting_transaction_addresses() {
+    fn check_verify_transactions_root() {
         let keypair = keychain::ethkey::generate_keypair();
-        let t1 = Transaction {
+        let t = Transaction {
             nonce: 0.into(),
             gas_price: 0.into(),
             gas: 100_000.into(),
             action: Action::Create,
-            value: 100.into(),
-            data: "601080600c6000396000f3006000355415600957005b60203560003555"
-                .from_hex()
-                .unwrap(),
-            transaction_type: DEFAULT_TRANSACTION_TYPE,
-            gas_price_bytes: Vec::new(),
-            gas_bytes: Vec::new(),
-            value_bytes: Vec::new(),
+            value: 101.into(),
+            data: vec![],
             nonce_bytes: Vec::new(),
-        }
-        .sign(&keypair.secret(), None);
-
-        let t2 = Transaction {
-            nonce: 1.into(),
-            gas_price: 0.into(),
-            gas: 100_000.into(),
-            action: Action::Create,
-            value: 100.into(),
-            data: "601080600c6000396000f3006000355415600957005b60203560003555"
-                .from_hex()
-                .unwrap(),
             gas_price_bytes: Vec::new(),
             gas_bytes: Vec::new(),
             value_bytes: Vec::new(),
-            nonce_bytes: Vec::new(),
             transaction_type: DEFAULT_TRANSACTION_TYPE,
         }
-        .sign(&keypair.secret(), None);
-        let t3 = Transaction {
-            nonce: 2.into(),
-            gas_price: 0.into(),
-            gas: 100_000.into(),
-            action: Action::Create,
-            value: 100.into(),
-            data: "601080600c6000396000f3006000355415600957005b60203560003555"
-                .from_hex()
-                .unwrap(),
-            gas_price_bytes: Vec::new(),
-            gas_bytes: Vec::new(),
-            value_bytes: Vec::new(),
-            nonce_bytes: Vec::new(),
-            transaction_type: DEFAULT_TRANSACTION_TYPE,
+        .sign(keypair.secret(), None);
+        assert_ne!(transactions_root(&[t.clone().into()]), transactions_root(&[]));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        // generated blocks commit to an empty transactions trie
+        let b2 = b1.add_block_with_transactions(iter::once(t.clone()));
+        let mut b2_fixed = b2.last().clone();
+        b2_fixed
+            .header
+            .set_transactions_root(transactions_root(&[t.clone().into()]));
+
+        let db = new_db();
+        let config = Config {
+            verify_transactions_root: true,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let mut batch = DBTransaction::new();
+        assert!(
+            bc.try_insert_block(&mut batch, &b1.last().encoded(), vec![])
+                .is_ok()
+        );
+        db.write(batch).unwrap();
+        bc.commit();
+
+        let mut batch = DBTransaction::new();
+        match bc.try_insert_block(&mut batch, &b2.last().encoded(), vec![]) {
+            Err(BlockImportError::TransactionsRootMismatch(mismatch)) => {
+                assert_eq!(mismatch.expected, b2.last().header().transactions_root().clone());
+                assert_eq!(mismatch.found, transactions_root(&[t.into()]));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!bc.is_known(&b2.last().hash()));
+
+        let mut batch = DBTransaction::new();
+        assert!(
+            bc.try_insert_block(&mut batch, &b2_fixed.encoded(), vec![])
+                .is_ok()
+        );
+        db.write(batch).unwrap();
+        bc.commit();
+        assert!(bc.is_known(&b2_fixed.hash()));
+    }
+
+    #[test]
+    fn check_compression_modes() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b1_hash = b1.last().hash();
+        let header = b1.last().header();
+
+        for mode in &[CompressionMode::Blocks, CompressionMode::None] {
+            let db = new_db();
+            let config = Config {
+                compression: *mode,
+                ..Default::default()
+            };
+            {
+                let bc = BlockChain::new(config.clone(), &genesis.last().encoded(), db.clone());
+                insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+                insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+            }
+
+            if *mode == CompressionMode::None {
+                let stored = db.get(db::COL_HEADERS, &b1_hash).unwrap().unwrap();
+                assert_eq!(&*stored, &*::rlp::encode(&header));
+            }
+
+            // reopen with both modes to read from the db instead of the caches
+            for read_mode in &[CompressionMode::Blocks, CompressionMode::None] {
+                let config = Config {
+                    compression: *read_mode,
+                    ..Default::default()
+                };
+                let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+                assert_eq!(bc.block_header(&b1_hash), Some(header.clone()));
+                assert_eq!(
+                    bc.block(&b1_hash).unwrap().into_inner(),
+                    b1.last().encoded()
+                );
+            }
         }
-        .sign(&keypair.secret(), None);
+    }
+
+    #[test]
+    fn check_compression_dictionary() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b1_hash = b1.last().hash();
+        let header = b1.last().header();
+        let raw_header = ::rlp::encode(&header).into_vec();
+        let dictionary = vec![
+            ::rlp::encode(header.parent_hash()).into_vec(),
+            ::rlp::encode(header.author()).into_vec(),
+        ];
+
+        let db = new_db();
+        let config = Config {
+            compression_dictionary: Some(dictionary.clone()),
+            ..Default::default()
+        };
+        {
+            let bc = BlockChain::new(config.clone(), &genesis.last().encoded(), db.clone());
+            assert!(!bc.has_dictionary_mismatch());
+            insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        }
+
+        // stored with the custom dictionary
+        let stored = db.get(db::COL_HEADERS, &b1_hash).unwrap().unwrap();
+        assert!(stored.len() < raw_header.len());
+        assert_eq!(
+            decompress(&stored, &DictionarySwapper::new(&dictionary)).into_vec(),
+            raw_header
+        );
+
+        // reopening with the same dictionary reads the block back
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        assert!(!bc.has_dictionary_mismatch());
+        assert_eq!(bc.block_header(&b1_hash), Some(header.clone()));
+        assert_eq!(
+            bc.block(&b1_hash).unwrap().into_inner(),
+            b1.last().encoded()
+        );
+
+        // reopening with the built-in dictionary is flagged, or refused when checked
+        let bc = BlockChain::new(Config::default(), &genesis.last().encoded(), db.clone());
+        assert!(bc.has_dictionary_mismatch());
+        match BlockChain::new_checked(Config::default(), &genesis.last().encoded(), db.clone()) {
+            Err(Error::DictionaryMismatch) => {}
+            _ => panic!("expected a dictionary mismatch"),
+        }
+        let config = Config {
+            compression_dictionary: Some(dictionary.clone()),
+            ..Default::default()
+        };
+        assert!(BlockChain::new_checked(config, &genesis.last().encoded(), db.clone()).is_ok());
+
+        // as is reopening a database created with the built-in dictionary with a custom one
+        let db = new_db();
+        BlockChain::new(Config::default(), &genesis.last().encoded(), db.clone());
+        let config = Config {
+            compression_dictionary: Some(dictionary),
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config.clone(), &genesis.last().encoded(), db.clone());
+        assert!(bc.has_dictionary_mismatch());
+        match BlockChain::new_checked(config, &genesis.last().encoded(), db.clone()) {
+            Err(Error::DictionaryMismatch) => {}
+            _ => panic!("expected a dictionary mismatch"),
+        }
+
+        // an oversized dictionary is refused instead of panicking
+        let config = Config {
+            compression_dictionary: Some(vec![vec![0xc0]; 127]),
+            ..Default::default()
+        };
+        match BlockChain::new_checked(config, &genesis.last().encoded(), new_db()) {
+            Err(Error::DictionaryTooLarge(oob)) => assert_eq!(oob.found, 127),
+            _ => panic!("expected an oversized dictionary"),
+        }
+    }
+
+    #[test]
+    fn check_cache_keys() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b1_hash = b1.last().hash();
+
+        let db = new_db();
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+            insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        }
+
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert!(!bc.cache_keys().block_headers.contains(&b1_hash));
+
+        bc.block_header_data(&b1_hash).unwrap();
+        bc.block_hash(1).unwrap();
+        let keys = bc.cache_keys();
+        assert!(keys.block_headers.contains(&b1_hash));
+        assert!(!keys.block_bodies.contains(&b1_hash));
+        assert!(keys.block_hashes.contains(&1));
+    }
+
+    #[test]
+    fn check_best_block_fast_path() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let best_hash = b2.last().hash();
+
+        let mut results = Vec::new();
+        for fast_path in &[true, false] {
+            let db = new_db();
+            let config = Config {
+                best_block_fast_path: *fast_path,
+                ..Default::default()
+            };
+            let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+            insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+            insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+            assert_eq!(bc.best_block_hash(), best_hash);
+
+            assert_eq!(bc.block_timestamp(&best_hash), Some(b2.last().header().timestamp()));
+            assert_eq!(bc.block_author(&best_hash), Some(*b2.last().header().author()));
+            assert_eq!(bc.cache_keys().block_headers.contains(&best_hash), !*fast_path);
+
+            let header = bc.block_header_data(&best_hash).unwrap();
+            let body = bc.block_body(&best_hash).unwrap();
+            let block = bc.block(&best_hash).unwrap();
+            assert_eq!(block.into_inner(), b2.last().encoded());
+
+            // without the fast path the reads go through the cache
+            let keys = bc.cache_keys();
+            assert_eq!(keys.block_headers.contains(&best_hash), !*fast_path);
+            assert_eq!(keys.block_bodies.contains(&best_hash), !*fast_path);
+
+            results.push((header.into_inner(), body.into_inner()));
+        }
+
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn check_invalidate_block() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b1_hash = b1.last().hash();
+        let b1_alt = genesis.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b1_alt.last().encoded(), vec![]);
+        let original = bc.block_header_data(&b1_hash).unwrap().into_inner();
+
+        // overwrite the stored header behind the cache's back
+        let replacement = ::rlp::encode(&b1_alt.last().header()).into_vec();
+        let mut batch = DBTransaction::new();
+        batch.put(db::COL_HEADERS, &b1_hash, &replacement);
+        db.write(batch).unwrap();
+        assert_eq!(bc.block_header_data(&b1_hash).unwrap().into_inner(), original);
+
+        bc.invalidate_block(&b1_hash);
+        assert!(!bc.cache_keys().block_headers.contains(&b1_hash));
+        assert_eq!(bc.block_header_data(&b1_hash).unwrap().into_inner(), replacement);
+
+        bc.block_hash(1).unwrap();
+        assert!(bc.cache_keys().block_hashes.contains(&1));
+        bc.invalidate_block_hashes(&[1]);
+        assert!(!bc.cache_keys().block_hashes.contains(&1));
+    }
+
+    #[test]
+    fn check_warm_cache() {
+        let genesis = BlockBuilder::genesis();
+        let generator = BlockGenerator::new(vec![genesis.add_blocks(5)]);
+
+        let db = new_db();
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            for block in generator {
+                insert_block(&db, &bc, &block.encoded(), vec![]);
+            }
+        }
+
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        let cold = bc.cache_size();
+        bc.warm_cache(0, 4);
+        let warm = bc.cache_size();
+        assert!(warm.blocks > cold.blocks);
+        assert!(warm.block_details > cold.block_details);
+
+        // numbers without a canonical hash don't stop the warming
+        let mut batch = DBTransaction::new();
+        Writable::delete::<H256, _>(&mut batch, db::COL_EXTRA, &2u64);
+        db.write(batch).unwrap();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        let b4_hash = bc.block_hash(4).unwrap();
+        bc.warm_cache(0, 4);
+        assert!(bc.cache_keys().block_headers.contains(&b4_hash));
+        assert!(bc.cache_keys().block_bodies.contains(&b4_hash));
+
+        // nothing is loaded into caches which are already full
+        let config = Config {
+            max_cache_size: 0,
+            ..Config::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let cold = bc.cache_size();
+        bc.warm_cache(0, 4);
+        assert_eq!(bc.cache_size().blocks, cold.blocks);
+
+        // warming stops as soon as the loaded blocks fill the caches
+        let cold = new_chain(&genesis.last().encoded(), db.clone()).cache_size();
+        let config = Config {
+            max_cache_size: cold.total() + 1,
+            ..Config::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let b1_hash = bc.block_hash(1).unwrap();
+        bc.warm_cache(0, 4);
+        assert!(bc.cache_keys().block_headers.contains(&genesis.last().hash()));
+        assert!(!bc.cache_keys().block_headers.contains(&b1_hash));
+    }
+
+    #[test]
+    fn check_repair_children() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b2b = b1.add_block_with_difficulty(9);
+
+        let genesis_hash = genesis.last().hash();
+        let b1_hash = b1.last().hash();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2b.last().encoded(), vec![]);
+        let b1_children = bc.block_details(&b1_hash).unwrap().children;
+        assert_eq!(b1_children.len(), 2);
+
+        // inject a duplicate and a bogus child
+        let mut batch = DBTransaction::new();
+        bc.add_child(&mut batch, genesis_hash, b1_hash);
+        bc.add_child(&mut batch, genesis_hash, H256::from(42));
+        bc.add_child(&mut batch, b1_hash, b2.last().hash());
+        db.write(batch).unwrap();
+        assert_eq!(
+            bc.block_details(&genesis_hash).unwrap().children,
+            vec![b1_hash, b1_hash, H256::from(42)]
+        );
+
+        let mut batch = DBTransaction::new();
+        bc.repair_children(&mut batch, &genesis_hash);
+        db.write(batch).unwrap();
+        assert_eq!(bc.block_details(&genesis_hash).unwrap().children, vec![b1_hash]);
+        assert_eq!(bc.block_details(&b1_hash).unwrap().children.len(), 3);
+
+        let mut batch = DBTransaction::new();
+        bc.repair_all_children(&mut batch, 0, 2);
+        db.write(batch).unwrap();
+        assert_eq!(bc.block_details(&b1_hash).unwrap().children, b1_children);
+
+        // the repaired details persist
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.block_details(&genesis_hash).unwrap().children, vec![b1_hash]);
+        assert_eq!(bc.block_details(&b1_hash).unwrap().children, b1_children);
+    }
+
+    #[test]
+    fn check_header_and_block_at() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b2b = b1.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2b.last().encoded(), vec![]);
+
+        for number in 0..3 {
+            let hash = bc.block_hash(number).unwrap();
+            assert_eq!(
+                bc.header_at(number).unwrap().hash(),
+                bc.block_header_data(&hash).unwrap().hash()
+            );
+            assert_eq!(
+                bc.block_at(number).unwrap().into_inner(),
+                bc.block(&hash).unwrap().into_inner()
+            );
+        }
+        assert_eq!(bc.header_at(2).unwrap().hash(), bc.best_block_hash());
+        assert!(bc.header_at(3).is_none());
+        assert!(bc.block_at(3).is_none());
+    }
+
+    #[test]
+    fn check_verify_export() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
+
+        let dump_of = |blocks: Vec<Bytes>| {
+            let mut writer = ExportWriter::new(Vec::new(), &bc.genesis_hash()).unwrap();
+            for block in blocks {
+                writer.write_block(&block).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let export = |numbers: &[u64]| {
+            dump_of(
+                numbers
+                    .iter()
+                    .map(|n| bc.block(&bc.block_hash(*n).unwrap()).unwrap().into_inner())
+                    .collect(),
+            )
+        };
+
+        let dump = export(&[1, 2, 3]);
+        assert_eq!(bc.export_blocks(1, 3, Vec::new()).unwrap(), dump);
+        assert!(bc.export_blocks(3, 4, Vec::new()).is_err());
+        let summary = bc.verify_export(&mut &dump[..]).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                first: 1,
+                last: 3,
+                count: 3,
+            }
+        );
+        let summary = bc.verify_export(&mut &export(&[0, 1])[..]).unwrap();
+        assert_eq!((summary.first, summary.last, summary.count), (0, 1, 2));
+
+        // truncated stream
+        match bc.verify_export(&mut &dump[..dump.len() - 1]) {
+            Err(ExportError::Io(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // blocks out of order
+        match bc.verify_export(&mut &export(&[1, 3, 2])[..]) {
+            Err(ExportError::Discontinuity {
+                index: 1,
+                ..
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // not a block
+        match bc.verify_export(&mut &dump_of(vec![vec![0xc2u8, 0x80, 0x80]])[..]) {
+            Err(ExportError::Decode(0, _)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // a dump may start anywhere on the chain
+        assert!(bc.verify_export(&mut &export(&[2, 3])[..]).is_ok());
+        // but it has to attach to it
+        let fork = BlockBuilder::genesis().add_block_with_difficulty(9).add_block();
+        match bc.verify_export(&mut &dump_of(vec![fork.last().encoded()])[..]) {
+            Err(ExportError::UnknownParent(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match bc.verify_export(&mut &dump_of(vec![])[..]) {
+            Err(ExportError::Empty) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // headerless streams are rejected
+        match bc.verify_export(&mut &b1.last().encoded()[..]) {
+            Err(ExportError::BadMagic) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // as are dumps of another network
+        let mut writer = ExportWriter::new(Vec::new(), &H256::from(1)).unwrap();
+        writer.write_block(&b1.last().encoded()).unwrap();
+        let other_dump = writer.finish().unwrap();
+        match bc.verify_export(&mut &other_dump[..]) {
+            Err(ExportError::GenesisMismatch {
+                expected,
+                found,
+            }) => {
+                assert_eq!(expected, bc.genesis_hash());
+                assert_eq!(found, H256::from(1));
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // the checksum has to match the blocks
+        let mut corrupted = dump.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x01;
+        match bc.verify_export(&mut &corrupted[..]) {
+            Err(ExportError::ChecksumMismatch {
+                ..
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(bc.best_block_number(), 3);
+    }
+
+    #[test]
+    fn check_are_known() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+
+        let db = new_db();
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+            insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        }
+
+        // reopen so that only some of the details are cached
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert!(bc.is_known(&b1.last().hash()));
+        let hashes = vec![
+            b3.last().hash(),
+            b1.last().hash(),
+            H256::from(1),
+            b2.last().hash(),
+            genesis.last().hash(),
+        ];
+        assert_eq!(bc.are_known(&hashes), vec![false, true, false, true, true]);
+        assert!(bc.are_known(&[]).is_empty());
+    }
+
+    #[test]
+    fn check_contains_canonical() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b2b = b1.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2b.last().encoded(), vec![]);
+
+        let b2_hash = b2.last().hash();
+        let b2b_hash = b2b.last().hash();
+        let (canonical, side) = if bc.best_block_hash() == b2_hash {
+            (b2_hash, b2b_hash)
+        } else {
+            (b2b_hash, b2_hash)
+        };
+
+        assert!(bc.contains_canonical(&genesis.last().hash()));
+        assert!(bc.contains_canonical(&b1.last().hash()));
+        assert!(bc.contains_canonical(&canonical));
+        assert!(bc.is_known(&side));
+        assert!(!bc.contains_canonical(&side));
+        assert!(!bc.contains_canonical(&H256::default()));
+    }
+
+    #[test]
+    fn check_transaction_index_in() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t1 = TestTx::new(0).sign(Some(&keypair));
+        let t2 = TestTx::new(1).sign(Some(&keypair));
+        let t3 = TestTx::new(2).sign(Some(&keypair));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_transactions(vec![t1.clone(), t2.clone(), t3.clone()]);
+        let b2 = b1.add_block();
+        let b1_hash = b1.last().hash();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+
+        assert_eq!(bc.transaction_index_in(&b1_hash, &t1.hash()), Some(0));
+        assert_eq!(bc.transaction_index_in(&b1_hash, &t2.hash()), Some(1));
+        assert_eq!(bc.transaction_index_in(&b1_hash, &t3.hash()), Some(2));
+        // the index agrees with the stored transaction address
+        let address = bc.transaction_address(&t3.hash()).unwrap();
+        assert_eq!(
+            bc.transaction_index_in(&address.block_hash, &t3.hash()),
+            Some(address.index)
+        );
+
+        assert_eq!(bc.transaction_index_in(&b2.last().hash(), &t1.hash()), None);
+        assert_eq!(bc.transaction_index_in(&H256::from(1), &t1.hash()), None);
+        assert_eq!(bc.transaction_index_in(&b1_hash, &H256::from(1)), None);
+    }
+
+    #[test]
+    fn check_latest_transactions() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t1 = TestTx::new(0).sign(Some(&keypair));
+        let t2 = TestTx::new(1).sign(Some(&keypair));
+        let t3 = TestTx::new(2).sign(Some(&keypair));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_transactions(vec![t1.clone(), t2.clone()]);
+        let b2 = b1.add_block();
+        let b3 = b2.add_block_with_transactions(iter::once(t3.clone()));
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert!(bc.latest_transactions(5).is_empty());
+
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b3.last().encoded(), vec![]);
+
+        let hashes = |n| {
+            bc.latest_transactions(n)
+                .into_iter()
+                .map(|t| t.hash())
+                .collect::<Vec<_>>()
+        };
+        assert!(hashes(0).is_empty());
+        assert_eq!(hashes(1), vec![t3.hash()]);
+        assert_eq!(hashes(2), vec![t3.hash(), t2.hash()]);
+        assert_eq!(hashes(10), vec![t3.hash(), t2.hash(), t1.hash()]);
+
+        let latest = bc.latest_transactions(1);
+        assert_eq!(latest[0].block_number, 3);
+        assert_eq!(latest[0].block_hash, b3.last().hash());
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
+        assert_eq!(bc.best_block_number(), 3);
+
+        let mut batch = DBTransaction::new();
+        assert!(bc.rewind_to(&mut batch, 4).is_err());
+        bc.rewind_to(&mut batch, 1).unwrap();
+        // nothing changes in memory until the batch is committed
+        assert_eq!(bc.best_block_number(), 3);
+        assert_eq!(bc.block_hash(3), Some(b3.last().hash()));
+        db.write(batch).unwrap();
+        bc.commit();
+
+        assert_eq!(bc.best_block_number(), 1);
+        assert_eq!(bc.best_block_hash(), b1.last().hash());
+        assert_eq!(bc.block_hash(1), Some(b1.last().hash()));
+        assert_eq!(bc.block_hash(2), None);
+        assert_eq!(bc.block_hash(3), None);
+        // rewound blocks are still known by hash
+        assert!(bc.is_known(&b3.last().hash()));
+        assert!(bc.block(&b3.last().hash()).is_some());
+
+        // the rewound state persists
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.best_block_number(), 1);
+        assert_eq!(bc.block_hash(2), None);
+
+        // the chain can be extended again from the new best block
+        let b2b = b1.add_block_with_difficulty(9);
+        insert_block(&db, &bc, &b2b.last().encoded(), vec![]);
+        assert_eq!(bc.best_block_hash(), b2b.last().hash());
+        assert_eq!(bc.block_hash(2), Some(b2b.last().hash()));
+    }
+
+    #[test]
+    fn check_block_timestamp() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+
+        let b1_hash = b1.last().hash();
+        let header = bc.block_header(&b1_hash).unwrap();
+        assert_eq!(bc.block_timestamp(&b1_hash), Some(header.timestamp()));
+        assert_eq!(bc.block_timestamp_at(1), Some(header.timestamp()));
+
+        // best block is served from the cached timestamp
+        let best_header = bc.block_header(&b2.last().hash()).unwrap();
+        assert_eq!(bc.block_timestamp(&b2.last().hash()), Some(best_header.timestamp()));
+        assert_eq!(bc.block_timestamp_at(2), Some(bc.best_block_timestamp()));
+
+        assert_eq!(bc.block_timestamp(&H256::from(1)), None);
+        assert_eq!(bc.block_timestamp_at(3), None);
+    }
+
+    #[test]
+    fn check_block_author() {
+        let author1 = Address::from(1);
+        let author2 = Address::from(2);
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with(|| {
+            BlockOptions {
+                author: author1,
+                ..Default::default()
+            }
+        });
+        let b2 = b1.add_block_with(|| {
+            BlockOptions {
+                author: author2,
+                ..Default::default()
+            }
+        });
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+
+        let b1_hash = b1.last().hash();
+        let header = bc.block_header(&b1_hash).unwrap();
+        assert_eq!(bc.block_author(&b1_hash), Some(*header.author()));
+        assert_eq!(bc.block_author(&b1_hash), Some(author1));
+
+        let b2_hash = b2.last().hash();
+        assert_eq!(bc.best_block_hash(), b2_hash);
+        assert_eq!(bc.block_author(&b2_hash), Some(author2));
+
+        assert_eq!(bc.block_author(&H256::from(1)), None);
+    }
+
+    #[test]
+    fn check_block_rlp() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t = Transaction {
+            nonce: 0.into(),
+            gas_price: 0.into(),
+            gas: 100_000.into(),
+            action: Action::Create,
+            value: 101.into(),
+            data: vec![],
+            nonce_bytes: Vec::new(),
+            gas_price_bytes: Vec::new(),
+            gas_bytes: Vec::new(),
+            value_bytes: Vec::new(),
+            transaction_type: DEFAULT_TRANSACTION_TYPE,
+        }
+        .sign(keypair.secret(), None);
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_transactions(iter::once(t.clone()));
+        let b2 = b1.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+
+        let b1_hash = b1.last().hash();
+        let rlp = bc.block_rlp(&b1_hash).unwrap();
+        assert_eq!(rlp, b1.last().encoded());
+        let view = BlockView::new(&rlp);
+        assert_eq!(view.header_view().hash(), b1_hash);
+        assert_eq!(view.transaction_hashes(), vec![t.hash()]);
+        assert_eq!(rlp, bc.block(&b1_hash).unwrap().into_inner());
+
+        // best block is served from the in-memory best block
+        let b2_hash = b2.last().hash();
+        assert_eq!(bc.best_block_hash(), b2_hash);
+        assert_eq!(bc.block_rlp(&b2_hash).unwrap(), b2.last().encoded());
+
+        assert_eq!(bc.block_rlp(&H256::from(1)), None);
+    }
+
+    #[test]
+    fn check_block_difficulty() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_difficulty(7);
+        let b1_side = genesis.add_block_with_difficulty(5);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b1_side.last().encoded(), vec![]);
+
+        let b1_hash = b1.last().hash();
+        let header = bc.block_header(&b1_hash).unwrap();
+        assert_eq!(bc.block_difficulty(&b1_hash), Some(*header.difficulty()));
+        assert_eq!(bc.block_difficulty(&b1_hash), Some(7.into()));
+        assert_eq!(bc.difficulty_at(1), Some(7.into()));
+
+        // side blocks are known by hash only
+        assert_eq!(bc.block_difficulty(&b1_side.last().hash()), Some(5.into()));
+        assert_eq!(
+            bc.difficulty_at(0),
+            Some(bc.block_header(&genesis.last().hash()).unwrap().difficulty().clone())
+        );
+
+        assert_eq!(bc.block_difficulty(&H256::from(1)), None);
+        assert_eq!(bc.difficulty_at(2), None);
+    }
+
+    #[test]
+    fn check_gap_work_items() {
+        let genesis = BlockBuilder::genesis();
+        let b3 = genesis.add_blocks(3);
+        let b10 = b3.add_blocks(7);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert!(bc.gap_work_items(4).is_empty());
+
+        // blocks 1-2 and 4-9 are missing
+        let mut batch = DBTransaction::new();
+        bc.insert_unordered_block(
+            &mut batch,
+            &b3.last().encoded(),
+            vec![],
+            Some(U256::from(3)),
+            false,
+            false,
+        );
+        bc.commit();
+        bc.insert_unordered_block(
+            &mut batch,
+            &b10.last().encoded(),
+            vec![],
+            Some(U256::from(10)),
+            true,
+            false,
+        );
+        bc.commit();
+        db.write(batch).unwrap();
+
+        assert_eq!(bc.best_block_number(), 10);
+        assert_eq!(bc.gap_work_items(4), vec![(6, 9), (4, 5), (1, 2)]);
+        assert_eq!(bc.gap_work_items(6), vec![(4, 9), (1, 2)]);
+        assert_eq!(
+            bc.gap_work_items(1),
+            vec![(9, 9), (8, 8), (7, 7), (6, 6), (5, 5), (4, 4), (2, 2), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn check_gap_closed_listener() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+
+        let db = new_db();
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            let mut batch = DBTransaction::new();
+            let parent_td = genesis.last().difficulty() + b1.last().difficulty();
+            bc.insert_unordered_block(
+                &mut batch,
+                &b2.last().encoded(),
+                vec![],
+                Some(parent_td),
+                true,
+                false,
+            );
+            bc.commit();
+            db.write(batch).unwrap();
+        }
+
+        // reopening finds the gap at block 1
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.best_ancient_number(), Some(0));
+        let closed = Arc::new(AtomicUsize::new(0));
+        let counter = closed.clone();
+        bc.on_gap_closed(Box::new(move || {
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+        }));
+
+        let mut batch = DBTransaction::new();
+        bc.insert_unordered_block(&mut batch, &b1.last().encoded(), vec![], None, false, true);
+        bc.commit();
+        db.write(batch).unwrap();
+
+        assert_eq!(closed.load(AtomicOrdering::SeqCst), 1);
+        assert_eq!(bc.best_ancient_block(), None);
+        assert!(db.get(db::COL_EXTRA, ExtraKey::Ancient.as_bytes()).unwrap().is_none());
+    }
+
+    #[test]
+    fn check_blocks_by_author() {
+        let alice = Address::from(1);
+        let bob = Address::from(2);
+        let authored_by = |author: Address| {
+            move || {
+                BlockOptions {
+                    author: author,
+                    ..Default::default()
+                }
+            }
+        };
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with(authored_by(alice));
+        let b2 = b1.add_block_with(authored_by(bob));
+        let b3 = b2.add_block_with(authored_by(alice));
+        let b4 = b3.add_block_with(authored_by(alice));
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3, &b4] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
+
+        assert_eq!(
+            bc.blocks_by_author(alice, 0, 4),
+            vec![b1.last().hash(), b3.last().hash(), b4.last().hash()]
+        );
+        assert_eq!(bc.blocks_by_author(alice, 2, 3), vec![b3.last().hash()]);
+        assert_eq!(bc.blocks_by_author(bob, 0, 10), vec![b2.last().hash()]);
+        assert!(bc.blocks_by_author(Address::from(3), 0, 4).is_empty());
+    }
+
+    #[test]
+    fn check_export_state_roots() {
+        let genesis = BlockBuilder::genesis();
+        let generator = BlockGenerator::new(vec![genesis.add_blocks(5)]);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in generator {
+            insert_block(&db, &bc, &block.encoded(), vec![]);
+        }
+
+        let roots = bc.export_state_roots(0, 5, 2);
+        let numbers: Vec<_> = roots.iter().map(|root| root.0).collect();
+        assert_eq!(numbers, vec![0, 2, 4, 5]);
+        for &(number, hash, state_root, total_difficulty) in &roots {
+            assert_eq!(bc.block_hash(number), Some(hash));
+            assert_eq!(bc.block_header_data(&hash).unwrap().state_root(), state_root);
+            let expected_td = genesis.last().difficulty() + U256::from(10 * number);
+            assert_eq!(total_difficulty, expected_td);
+        }
+
+        // aligned ends aren't repeated, and unknown blocks are skipped
+        let numbers: Vec<_> = bc.export_state_roots(1, 5, 2).iter().map(|r| r.0).collect();
+        assert_eq!(numbers, vec![1, 3, 5]);
+        let numbers: Vec<_> = bc.export_state_roots(4, 8, 3).iter().map(|r| r.0).collect();
+        assert_eq!(numbers, vec![4]);
+        assert!(bc.export_state_roots(3, 2, 1).is_empty());
+    }
+
+    #[test]
+    fn check_nearest_canonical_ancestor() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+        let f2 = b1.add_block_with_difficulty(9);
+        let f3 = f2.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3, &f2, &f3] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
+        assert_eq!(bc.best_block_hash(), b3.last().hash());
+
+        let b1_hash = b1.last().hash();
+        assert_eq!(bc.nearest_canonical_ancestor(f3.last().hash()), Some(b1_hash));
+        assert_eq!(bc.nearest_canonical_ancestor(f2.last().hash()), Some(b1_hash));
+        assert_eq!(
+            bc.nearest_canonical_ancestor(b2.last().hash()),
+            Some(b2.last().hash())
+        );
+        assert_eq!(
+            bc.nearest_canonical_ancestor(genesis.last().hash()),
+            Some(genesis.last().hash())
+        );
+        assert_eq!(bc.nearest_canonical_ancestor(H256::from(1)), None);
+    }
+
+    #[test]
+    fn check_with_chunked_batch() {
+        let genesis = BlockBuilder::genesis();
+        let generator = BlockGenerator::new(vec![genesis.add_blocks(10)]);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        let mut hashes = vec![genesis.last().hash()];
+        for block in generator {
+            hashes.push(block.hash());
+            insert_block(&db, &bc, &block.encoded(), vec![]);
+        }
+
+        // drop the number index, then rebuild it one entry per call
+        let mut batch = DBTransaction::new();
+        for number in 1..11u64 {
+            Writable::delete::<H256, _>(&mut batch, db::COL_EXTRA, &number);
+        }
+        db.write(batch).unwrap();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.block_hash(1), None);
+
+        let mut next = 1u64;
+        let mut indexed_on_disk = Vec::new();
+        // the first entry reaches the disk with the 7th one
+        let commits = bc.with_chunked_batch(7, |batch| {
+            indexed_on_disk.push(db.read::<H256, _>(db::COL_EXTRA, &1u64).is_some());
+            Writable::write(batch, db::COL_EXTRA, &next, &hashes[next as usize]);
+            next += 1;
+            next < 11
+        });
+
+        // written after 7 entries and once more for the remaining 3
+        assert_eq!(commits, 2);
+        let mut expected = vec![false; 7];
+        expected.extend(vec![true; 3]);
+        assert_eq!(indexed_on_disk, expected);
+        for number in 1..11 {
+            assert_eq!(bc.block_hash(number), Some(hashes[number as usize]));
+        }
+    }
+
+    #[test]
+    fn check_canonical_range_hashes() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b3 = b1.add_blocks(2);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        // block 2 is missing
+        let mut batch = DBTransaction::new();
+        bc.insert_unordered_block(
+            &mut batch,
+            &b3.last().encoded(),
+            vec![],
+            Some(U256::from(30)),
+            true,
+            false,
+        );
+        bc.commit();
+        db.write(batch).unwrap();
+
+        assert_eq!(
+            bc.canonical_range_hashes(0, 4),
+            vec![
+                Some(genesis.last().hash()),
+                Some(b1.last().hash()),
+                None,
+                Some(b3.last().hash()),
+                None,
+            ]
+        );
+        // the same answers from the database alone
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.canonical_range_hashes(2, 3), vec![None, Some(b3.last().hash())]);
+        assert!(bc.canonical_range_hashes(3, 2).is_empty());
+    }
+
+    #[test]
+    fn check_verify_canonical_links() {
+        let genesis = BlockBuilder::genesis();
+        let b3 = genesis.add_blocks(3);
+        let b1a = genesis.add_block_with_difficulty(9);
+        let b2a = b1a.add_block_with_difficulty(9);
+        let generator = BlockGenerator::new(vec![b3, b1a, b2a.clone()]);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in generator {
+            insert_block(&db, &bc, &block.encoded(), vec![]);
+        }
+        assert_eq!(bc.verify_canonical_links(0, 3), Ok(()));
+        assert_eq!(bc.verify_canonical_links(2, 1), Ok(()));
+
+        // point number 2 at the side block, as a crash mid-reorg could leave it
+        let mut batch = DBTransaction::new();
+        batch.write(db::COL_EXTRA, &2u64, &b2a.last().hash());
+        db.write(batch).unwrap();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.verify_canonical_links(0, 3), Err(vec![2, 3]));
+        assert_eq!(bc.verify_canonical_links(3, 3), Err(vec![3]));
+        assert_eq!(bc.verify_canonical_links(0, 1), Ok(()));
+        assert_eq!(bc.verify_canonical_links(3, 4), Err(vec![3, 4]));
+    }
+
+    #[test]
+    fn check_best_block_hint() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+
+        let db = new_db();
+        let b3_details;
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            for block in &[&b1, &b2, &b3] {
+                insert_block(&db, &bc, &block.last().encoded(), vec![]);
+            }
+            b3_details = bc.block_details(&b3.last().hash()).unwrap();
+        }
+        let read_hint = || {
+            let raw = db
+                .get(db::COL_EXTRA, ExtraKey::BestHint.as_bytes())
+                .unwrap()
+                .unwrap();
+            ::rlp::decode::<BestBlockHint>(&raw)
+        };
+        // the hint is written along with the best block
+        assert_eq!(
+            read_hint(),
+            BestBlockHint {
+                hash: b3.last().hash(),
+                number: 3,
+                total_difficulty: b3_details.total_difficulty,
+            }
+        );
+
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.best_block_number(), 3);
+        assert_eq!(bc.best_block_hash(), b3.last().hash());
+        assert_eq!(bc.best_block_total_difficulty(), b3_details.total_difficulty);
+
+        // a valid hint is taken instead of the best block details
+        let mut batch = DBTransaction::new();
+        let forged = BestBlockHint {
+            hash: b3.last().hash(),
+            number: 3,
+            total_difficulty: b3_details.total_difficulty + U256::from(1),
+        };
+        batch.put(db::COL_EXTRA, ExtraKey::BestHint.as_bytes(), &::rlp::encode(&forged));
+        db.write(batch).unwrap();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.best_block_number(), 3);
+        assert_eq!(bc.best_block_total_difficulty(), b3_details.total_difficulty + U256::from(1));
+
+        // a hint for another block falls back to the details of the best block
+        let mut batch = DBTransaction::new();
+        let stale = BestBlockHint {
+            hash: b1.last().hash(),
+            number: 1,
+            total_difficulty: 0.into(),
+        };
+        batch.put(db::COL_EXTRA, ExtraKey::BestHint.as_bytes(), &::rlp::encode(&stale));
+        db.write(batch).unwrap();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.best_block_number(), 3);
+        assert_eq!(bc.best_block_hash(), b3.last().hash());
+        assert_eq!(bc.best_block_total_difficulty(), b3_details.total_difficulty);
+
+        // rewinding moves the hint along with the best block
+        let mut batch = DBTransaction::new();
+        bc.rewind_to(&mut batch, 2).unwrap();
+        db.write(batch).unwrap();
+        bc.commit();
+        let b2_details = bc.block_details(&b2.last().hash()).unwrap();
+        assert_eq!(
+            read_hint(),
+            BestBlockHint {
+                hash: b2.last().hash(),
+                number: 2,
+                total_difficulty: b2_details.total_difficulty,
+            }
+        );
+    }
+
+    #[test]
+    fn check_receipts_in_range() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        let receipt = Receipt::new(
+            H256::default(),
+            10_000.into(),
+            U256::zero(),
+            vec![],
+            Bytes::default(),
+            String::default(),
+        );
+
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![receipt.clone()]);
+        insert_block(&db, &bc, &b3.last().encoded(), vec![]);
+
+        // genesis has no receipts and number 4 is unknown, both are skipped
+        let receipts = bc.receipts_in_range(0, 4).collect::<Vec<_>>();
+        assert_eq!(receipts.len(), 3);
+        assert_eq!(receipts[0].0, 1);
+        assert_eq!(receipts[0].1, b1.last().hash());
+        assert!(receipts[0].2.receipts.is_empty());
+        assert_eq!(receipts[1].0, 2);
+        assert_eq!(receipts[1].1, b2.last().hash());
+        assert_eq!(receipts[1].2.receipts, vec![receipt]);
+        assert_eq!(receipts[2].0, 3);
+        assert_eq!(receipts[2].1, b3.last().hash());
+        assert!(receipts[2].2.receipts.is_empty());
+    }
+
+    #[test]
+    fn test_fork_transaction_addresses() {
+        let t1 = Transaction {
+            nonce: 0.into(),
+            gas_price: 0.into(),
+            gas: 100_000.into(),
+            action: Action::Create,
+            value: 100.into(),
+            data: "601080600c6000396000f3006000355415600957005b60203560003555"
+                .from_hex()
+                .unwrap(),
+            transaction_type: DEFAULT_TRANSACTION_TYPE,
+            nonce_bytes: Vec::new(),
+            gas_bytes: Vec::new(),
+            gas_price_bytes: Vec::new(),
+            value_bytes: Vec::new(),
+        }
+        .sign(keychain::ethkey::generate_keypair().secret(), None);
+
+        let t1_hash = t1.hash();
+
+        let genesis = BlockBuilder::genesis();
+        let b1a = genesis.add_block_with_transactions(iter::once(t1));
+        let b1b = genesis.add_block_with_difficulty(9);
+        let b2 = b1b.add_block();
+
+        let b1a_hash = b1a.last().hash();
+        let b2_hash = b2.last().hash();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+
+        let mut batch = DBTransaction::new();
+        let _ = bc.insert_block(&mut batch, &b1a.last().encoded(), vec![]);
+        bc.commit();
+        let _ = bc.insert_block(&mut batch, &b1b.last().encoded(), vec![]);
+        bc.commit();
+        db.write(batch).unwrap();
+
+        assert_eq!(bc.best_block_hash(), b1a_hash);
+        assert_eq!(
+            bc.transaction_address(&t1_hash),
+            Some(TransactionAddress {
+                block_hash: b1a_hash,
+                index: 0,
+            })
+        );
+
+        // now let's make forked chain the canon chain
+        let mut batch = DBTransaction::new();
+        let _ = bc.insert_block(&mut batch, &b2.last().encoded(), vec![]);
+        bc.commit();
+        db.write(batch).unwrap();
+
+        // Transaction should be retracted
+        assert_eq!(bc.best_block_hash(), b2_hash);
+        assert_eq!(bc.transaction_address(&t1_hash), None);
+    }
+
+    #[test]
+    fn test_overwriting_transaction_addresses() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let data = "601080600c6000396000f3006000355415600957005b60203560003555"
+            .from_hex()
+            .unwrap();
+        let t1 = TestTx::new(0)
+            .value(100)
+            .data(data.clone())
+            .sign(Some(&keypair));
+        let t2 = TestTx::new(1)
+            .value(100)
+            .data(data.clone())
+            .sign(Some(&keypair));
+        let t3 = TestTx::new(2).value(100).data(data).sign(Some(&keypair));
 
         let genesis = BlockBuilder::genesis();
         let b1a = genesis.add_block_with_transactions(vec![t1.clone(), t2.clone()]);
@@ -2125,6 +4758,27 @@ mod tests {
         assert_eq!(r3b_3a.ancestor, b2_hash);
         assert_eq!(r3b_3a.blocks, [b3b_hash, b3a_hash]);
         assert_eq!(r3b_3a.index, 1);
+
+        // test route accessors
+        let routes = [
+            &r0_1, &r0_2, &r1_3a, &r1_3b, &r3a_3b, &r1_0, &r2_0, &r3a_1, &r3b_1, &r3b_3a,
+        ];
+        for route in &routes {
+            assert_eq!(route.retracted(), &route.blocks[..route.index]);
+            assert_eq!(route.enacted(), &route.blocks[route.index..]);
+        }
+        assert!(r0_2.retracted().is_empty());
+        assert_eq!(r0_2.enacted(), &[b1_hash, b2_hash]);
+        assert_eq!(r2_0.retracted(), &[b2_hash, b1_hash]);
+        assert!(r2_0.enacted().is_empty());
+        assert_eq!(r3a_3b.retracted(), &[b3a_hash]);
+        assert_eq!(r3a_3b.enacted(), &[b3b_hash]);
+
+        assert!(r3a_3b.is_reorg());
+        assert!(r3b_3a.is_reorg());
+        assert!(!r0_2.is_reorg());
+        assert!(!r2_0.is_reorg());
+        assert!(!r3a_1.is_reorg());
     }
 
     #[test]
@@ -2153,6 +4807,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reopen_with_different_genesis() {
+        let genesis = BlockBuilder::genesis();
+        let mut other_genesis = genesis.last().clone();
+        other_genesis.header.set_timestamp(1);
+
+        let db = new_db();
+        let genesis_bytes = genesis.last().encoded();
+        assert!(BlockChain::new_checked(Config::default(), &genesis_bytes, db.clone()).is_ok());
+        // reopening with the same genesis is fine
+        assert!(BlockChain::new_checked(Config::default(), &genesis_bytes, db.clone()).is_ok());
+
+        match BlockChain::new_checked(Config::default(), &other_genesis.encoded(), db.clone()) {
+            Err(Error::GenesisMismatch(mis)) => {
+                assert_eq!(mis.expected, other_genesis.hash());
+                assert_eq!(mis.found, genesis.last().hash());
+            }
+            _ => panic!("opening with a different genesis should fail"),
+        }
+    }
+
     #[test]
     fn can_contain_arbitrary_block_sequence() {
         let bc = generate_dummy_blockchain(50);
@@ -2207,12 +4882,14 @@ mod tests {
         let transactions = bc.transactions(&b1_hash).unwrap();
         assert_eq!(transactions.len(), 2);
         for t in transactions {
+            assert!(bc.has_transaction(&t.hash()));
             assert_eq!(
                 bc.transaction(&bc.transaction_address(&t.hash()).unwrap())
                     .unwrap(),
                 t
             );
         }
+        assert!(!bc.has_transaction(&H256::from(1)));
     }
 
     fn insert_block(
@@ -2437,6 +5114,156 @@ mod tests {
                 log_index: 0,
             }]
         );
+
+        // custom chunk size and a dedicated pool yield the same logs
+        let config = Config {
+            log_scan_chunk_size: 1,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let pool = ThreadPool::new(Configuration::new().num_threads(2)).unwrap();
+        bc.set_log_scan_pool(Some(Arc::new(pool)));
+        assert_eq!(bc.logs(vec![1, 2], |_| true, None), logs1);
+        assert_eq!(bc.logs(vec![1, 2], |_| true, Some(1)), logs2);
+    }
+
+    // Database which counts reads of block bodies.
+    struct BodyReadCountingDb {
+        inner: MockDbRepository,
+        body_reads: AtomicUsize,
+    }
+
+    impl KeyValueDB for BodyReadCountingDb {
+        fn get(&self, db_name: &str, key: &[u8]) -> ::kvdb::Result<Option<DBValue>> {
+            if db_name == db::COL_BODIES {
+                self.body_reads.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            self.inner.get(db_name, key)
+        }
+
+        fn keys(&self) -> Option<Vec<String>> { self.inner.keys() }
+
+        fn write_buffered(&self, transaction: DBTransaction) {
+            self.inner.write_buffered(transaction)
+        }
+
+        fn iter(&self, db_name: &'static str) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)>> {
+            self.inner.iter(db_name)
+        }
+
+        fn get_by_prefix(&self, db_name: &'static str, prefix: &[u8]) -> Option<Box<[u8]>> {
+            self.inner.get_by_prefix(db_name, prefix)
+        }
+
+        fn iter_from_prefix<'a>(
+            &'a self,
+            db_name: &'static str,
+            prefix: &'static [u8],
+        ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>
+        {
+            self.inner.iter_from_prefix(db_name, prefix)
+        }
+    }
+
+    #[test]
+    fn test_logs_limit_stops_scanning() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t = Transaction {
+            nonce: 0.into(),
+            gas_price: 0.into(),
+            gas: 100_000.into(),
+            action: Action::Create,
+            value: 101.into(),
+            data: vec![],
+            nonce_bytes: Vec::new(),
+            gas_price_bytes: Vec::new(),
+            gas_bytes: Vec::new(),
+            value_bytes: Vec::new(),
+            transaction_type: DEFAULT_TRANSACTION_TYPE,
+        }
+        .sign(keypair.secret(), None);
+        let receipt = Receipt::new(
+            H256::default(),
+            10_000.into(),
+            U256::zero(),
+            vec![LogEntry {
+                address: Default::default(),
+                topics: vec![],
+                data: vec![1],
+            }],
+            Bytes::default(),
+            String::default(),
+        );
+
+        let mut db_configs = Vec::new();
+        for db_name in db::DB_NAMES.to_vec() {
+            db_configs.push(db_name.into());
+        }
+        let counting = Arc::new(BodyReadCountingDb {
+            inner: MockDbRepository::init(db_configs),
+            body_reads: AtomicUsize::new(0),
+        });
+        let db: Arc<KeyValueDB> = counting.clone();
+
+        let genesis = BlockBuilder::genesis();
+        let blocks = genesis.add_blocks_with(10, || {
+            BlockOptions {
+                transactions: vec![t.clone()],
+                ..Default::default()
+            }
+        });
+        {
+            let bc = new_chain(&genesis.last().encoded(), db.clone());
+            for block in BlockGenerator::new(vec![blocks]) {
+                insert_block(&db, &bc, &block.encoded(), vec![receipt.clone()]);
+            }
+        }
+
+        // reopen so that bodies are read from the database
+        let config = Config {
+            log_scan_chunk_size: 2,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        let reads = counting.body_reads.load(AtomicOrdering::SeqCst);
+        let logs = bc.logs((1..11).collect(), |_| true, Some(1));
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_number, 10);
+        // only the first chunk of the newest blocks is scanned
+        assert!(counting.body_reads.load(AtomicOrdering::SeqCst) - reads <= 2);
+
+        let logs = bc.logs((1..11).collect(), |_| true, None);
+        assert_eq!(logs.len(), 10);
+        assert!(counting.body_reads.load(AtomicOrdering::SeqCst) - reads >= 8);
+    }
+
+    #[test]
+    fn check_latest_block_with_bloom() {
+        let bloom_a = Bloom::from([0x01u8; 256]);
+        let bloom_b = Bloom::from([0x02u8; 256]);
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_bloom(bloom_a);
+        let b2 = b1.add_block_with_bloom(bloom_b);
+        // spans several bloom groups
+        let b40 = b2.add_blocks(38);
+        let b41 = b40.add_block_with_bloom(bloom_a);
+        let b42 = b41.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.latest_block_with_bloom(&bloom_a, 0), None);
+        for block in BlockGenerator::new(vec![b1, b2, b40, b41, b42]) {
+            insert_block(&db, &bc, &block.encoded(), vec![]);
+        }
+        assert_eq!(bc.best_block_number(), 42);
+
+        assert_eq!(bc.blocks_with_bloom(&bloom_a, 0, 42), vec![1, 41]);
+        assert_eq!(bc.latest_block_with_bloom(&bloom_a, 0), Some(41));
+        assert_eq!(bc.latest_block_with_bloom(&bloom_a, 41), Some(41));
+        assert_eq!(bc.latest_block_with_bloom(&bloom_a, 42), None);
+        assert_eq!(bc.latest_block_with_bloom(&bloom_b, 0), Some(2));
+        assert_eq!(bc.latest_block_with_bloom(&bloom_b, 3), None);
+        assert_eq!(bc.latest_block_with_bloom(&bloom_b, 100), None);
     }
 
     #[test]
@@ -2509,6 +5336,29 @@ mod tests {
         assert_eq!(blocks_ba, vec![3]);
     }
 
+    #[test]
+    fn test_bloom_filter_range_bounds() {
+        let bloom: Bloom = "00000020000000000000000000000000000000000000000002000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000040000000000000010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000008000400000000000000000000002000".into();
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block_with_bloom(bloom);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        insert_block(&db, &bc, &b2.last().encoded(), vec![]);
+
+        assert_eq!(bc.blocks_with_bloom(&bloom, 0, 2), vec![2]);
+        // inverted range
+        assert!(bc.blocks_with_bloom(&bloom, 2, 0).is_empty());
+        assert!(bc.blocks_with_bloom(&bloom, 5, 1).is_empty());
+        // range beyond the best block is clamped
+        assert_eq!(bc.blocks_with_bloom(&bloom, 0, 1_000_000), vec![2]);
+        assert_eq!(bc.blocks_with_bloom(&bloom, 2, u64::max_value()), vec![2]);
+        assert!(bc.blocks_with_bloom(&bloom, 3, 10).is_empty());
+    }
+
     #[test]
     fn test_insert_unordered() {
         let bloom_b1: Bloom = "00000020000000000000000000000000000000000000000002000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000040000000000000010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000008000400000000000000000000002000".into();
@@ -2584,6 +5434,347 @@ mod tests {
         assert_eq!(bc.best_block_number(), 5);
     }
 
+    #[test]
+    fn check_insert_header_and_attach_body() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t1 = TestTx::new(0).sign(Some(&keypair));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_transactions(iter::once(t1.clone()));
+        let b1_hash = b1.last().hash();
+        let b1_header = ::rlp::encode(&b1.last().header()).into_vec();
+        let b2 = b1.add_block();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+
+        let mut batch = DBTransaction::new();
+        bc.insert_header(&mut batch, &b1_header).unwrap();
+        db.write(batch).unwrap();
+
+        assert!(bc.is_known(&b1_hash));
+        assert_eq!(bc.block_header(&b1_hash), Some(b1.last().header()));
+        assert_eq!(bc.block_details(&b1_hash).unwrap().number, 1);
+        assert!(bc
+            .block_details(&genesis.last().hash())
+            .unwrap()
+            .children
+            .contains(&b1_hash));
+        assert!(bc.block_body(&b1_hash).is_none());
+        assert!(bc.block(&b1_hash).is_none());
+        // header-only blocks don't touch the canonical chain
+        assert_eq!(bc.best_block_number(), 0);
+        assert_eq!(bc.block_hash(1), None);
+
+        let mut batch = DBTransaction::new();
+        match bc.insert_header(&mut batch, &b1_header) {
+            Err(BlockImportError::Import(ImportError::AlreadyInChain)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let orphan = ::rlp::encode(&b2.add_block().last().header()).into_vec();
+        match bc.insert_header(&mut batch, &orphan) {
+            Err(BlockImportError::Block(BlockError::UnknownParent(_))) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // the body has to match the header
+        let empty_body = BlockChain::block_to_body(&b2.last().encoded());
+        match bc.attach_body(&mut batch, &b1_hash, &empty_body) {
+            Err(BlockImportError::Block(BlockError::InvalidTransactionsRoot(_))) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let body = BlockChain::block_to_body(&b1.last().encoded());
+        match bc.attach_body(&mut batch, &H256::from(1), &body) {
+            Err(BlockImportError::Other(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        bc.attach_body(&mut batch, &b1_hash, &body).unwrap();
+        db.write(batch).unwrap();
+
+        assert_eq!(bc.block(&b1_hash).unwrap().into_inner(), b1.last().encoded());
+        assert_eq!(bc.block_body(&b1_hash).unwrap().transaction_hashes(), vec![t1.hash()]);
+        let mut batch = DBTransaction::new();
+        match bc.attach_body(&mut batch, &b1_hash, &body) {
+            Err(BlockImportError::Import(ImportError::AlreadyInChain)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn check_header_first_block_becomes_best() {
+        let keypair = keychain::ethkey::generate_keypair();
+        let t1 = TestTx::new(0).sign(Some(&keypair));
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block_with_transactions(iter::once(t1.clone()));
+        let b2 = b1.add_block();
+        let (b1_hash, b2_hash) = (b1.last().hash(), b2.last().hash());
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+
+        let mut batch = DBTransaction::new();
+        bc.insert_header(&mut batch, &::rlp::encode(&b1.last().header())).unwrap();
+        db.write(batch).unwrap();
+        let mut batch = DBTransaction::new();
+        bc.insert_header(&mut batch, &::rlp::encode(&b2.last().header())).unwrap();
+        let body = BlockChain::block_to_body(&b1.last().encoded());
+        bc.attach_body(&mut batch, &b1_hash, &body).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(bc.best_block_number(), 0);
+
+        // importing the full blocks completes them
+        for block in &[b1.last(), b2.last()] {
+            let mut batch = DBTransaction::new();
+            let route = bc.try_insert_block(&mut batch, &block.encoded(), vec![]).unwrap();
+            assert_eq!(route.enacted, vec![block.hash()]);
+            db.write(batch).unwrap();
+            bc.commit();
+        }
+        assert_eq!(bc.best_block_hash(), b2_hash);
+        assert_eq!(bc.block_hash(1), Some(b1_hash));
+        assert_eq!(bc.block_hash(2), Some(b2_hash));
+        assert_eq!(
+            bc.transaction_address(&t1.hash()),
+            Some(TransactionAddress {
+                block_hash: b1_hash,
+                index: 0,
+            })
+        );
+        assert!(bc.block_receipts(&b1_hash).is_some());
+        assert_eq!(bc.block_details(&genesis.last().hash()).unwrap().children, vec![b1_hash]);
+        assert_eq!(bc.block_details(&b1_hash).unwrap().children, vec![b2_hash]);
+
+        // and only once
+        let mut batch = DBTransaction::new();
+        match bc.try_insert_block(&mut batch, &b2.last().encoded(), vec![]) {
+            Err(BlockImportError::Import(ImportError::AlreadyInChain)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn check_prune_receipts_below() {
+        let genesis = BlockBuilder::genesis();
+        let blocks = genesis.add_blocks(10);
+        let receipt = Receipt::new(
+            H256::default(),
+            10_000.into(),
+            U256::zero(),
+            vec![],
+            Bytes::default(),
+            String::default(),
+        );
+
+        let db = new_db();
+        let config = Config {
+            receipts_retained_depth: 4,
+            ..Default::default()
+        };
+        let bc = BlockChain::new(config.clone(), &genesis.last().encoded(), db.clone());
+        let mut hashes = vec![genesis.last().hash()];
+        for block in BlockGenerator::new(vec![blocks]) {
+            hashes.push(block.hash());
+            insert_block(&db, &bc, &block.encoded(), vec![receipt.clone()]);
+        }
+        // populate the cache
+        assert!(bc.block_receipts(&hashes[3]).is_some());
+
+        // receipts of the 4 most recent blocks, #6 onwards, are retained
+        let mut batch = DBTransaction::new();
+        assert_eq!(bc.prune_receipts_below(&mut batch, 7), 0);
+        assert!(batch.is_empty());
+
+        // the genesis has no receipts stored
+        assert_eq!(bc.prune_receipts_below(&mut batch, 6), 5);
+        // reads before the batch is written don't leave stale entries behind
+        assert!(bc.block_receipts(&hashes[4]).is_some());
+        db.write(batch).unwrap();
+        bc.commit();
+        for number in 1..6 {
+            assert!(bc.block_receipts(&hashes[number]).is_none());
+        }
+        for number in 6..11 {
+            assert!(bc.block_receipts(&hashes[number]).is_some());
+        }
+
+        // and they are gone from the database
+        let bc = BlockChain::new(config, &genesis.last().encoded(), db.clone());
+        assert!(bc.block_receipts(&hashes[3]).is_none());
+        assert!(bc.block_receipts(&hashes[6]).is_some());
+        let mut batch = DBTransaction::new();
+        assert_eq!(bc.prune_receipts_below(&mut batch, 6), 0);
+    }
+
+    #[test]
+    fn check_raw_extra() {
+        let genesis = BlockBuilder::genesis();
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        assert_eq!(bc.get_raw_extra(b"plugin:counter"), None);
+
+        let mut batch = DBTransaction::new();
+        bc.put_raw_extra(&mut batch, b"plugin:counter", b"42").unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(bc.get_raw_extra(b"plugin:counter"), Some(b"42".to_vec()));
+
+        // markers and per-block data can't be overwritten
+        let best = genesis.last().hash();
+        let mut batch = DBTransaction::new();
+        assert!(
+            bc.put_raw_extra(&mut batch, ExtraKey::Best.as_bytes(), b"")
+                .is_err()
+        );
+        assert!(bc.put_raw_extra(&mut batch, b"besthint2", b"").is_err());
+        let mut details_key = vec![ExtrasIndex::BlockDetails as u8];
+        details_key.extend_from_slice(&best);
+        assert!(bc.put_raw_extra(&mut batch, &details_key, b"").is_err());
+        assert!(bc.put_raw_extra(&mut batch, b"", b"").is_err());
+        assert!(batch.is_empty());
+        assert_eq!(bc.get_raw_extra(ExtraKey::Best.as_bytes()), Some(best.to_vec()));
+    }
+
+    #[test]
+    fn check_prune_orphans() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b2 = b1.add_block();
+        let b3 = b2.add_block();
+        let b4 = b3.add_block();
+        // a side branch below the pruning point and one reaching it
+        let f2 = b1.add_block_with_difficulty(9);
+        let f3 = f2.add_block_with_difficulty(9);
+        let h4 = b3.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        for block in &[&b1, &b2, &b3, &b4, &f2, &f3, &h4] {
+            insert_block(&db, &bc, &block.last().encoded(), vec![]);
+        }
+        assert_eq!(bc.best_block_hash(), b4.last().hash());
+        assert!(bc.is_known(&f3.last().hash()));
+
+        let mut batch = DBTransaction::new();
+        assert_eq!(bc.prune_orphans(&mut batch, 4), 2);
+        // reads before the batch is written don't leave stale entries behind
+        assert!(bc.block(&f2.last().hash()).is_some());
+        assert!(bc.block_details(&f3.last().hash()).is_some());
+        db.write(batch).unwrap();
+        bc.commit();
+
+        for orphan in &[&f2, &f3] {
+            let hash = orphan.last().hash();
+            assert!(!bc.is_known(&hash));
+            assert!(bc.block(&hash).is_none());
+        }
+        assert!(bc.is_known(&h4.last().hash()));
+        for block in &[&b1, &b2, &b3, &b4] {
+            assert!(bc.block(&block.last().hash()).is_some());
+        }
+        assert_eq!(bc.block_details(&b1.last().hash()).unwrap().children, vec![b2.last().hash()]);
+        assert_eq!(bc.best_block_hash(), b4.last().hash());
+
+        // nothing left to prune
+        let mut batch = DBTransaction::new();
+        assert_eq!(bc.prune_orphans(&mut batch, 4), 0);
+    }
+
+    #[test]
+    fn check_try_insert_known_block() {
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b1b = genesis.add_block_with_difficulty(9);
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+
+        let mut batch = DBTransaction::new();
+        let route = bc
+            .try_insert_block(&mut batch, &b1.last().encoded(), vec![])
+            .unwrap();
+        assert_eq!(route.enacted, vec![b1.last().hash()]);
+        db.write(batch).unwrap();
+        bc.commit();
+
+        let mut batch = DBTransaction::new();
+        match bc.try_insert_block(&mut batch, &b1.last().encoded(), vec![]) {
+            Err(BlockImportError::Import(ImportError::AlreadyInChain)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // a non-canonical block is still imported
+        let route = bc
+            .try_insert_block(&mut batch, &b1b.last().encoded(), vec![])
+            .unwrap();
+        assert_eq!(route.omitted, vec![b1b.last().hash()]);
+        db.write(batch).unwrap();
+        bc.commit();
+    }
+
+    #[test]
+    fn check_has_pending_transition() {
+        use engines::epoch::PendingTransition;
+
+        let genesis = BlockBuilder::genesis();
+        let b1 = genesis.add_block();
+        let b1_hash = b1.last().hash();
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        insert_block(&db, &bc, &b1.last().encoded(), vec![]);
+        assert!(!bc.has_pending_transition(&b1_hash));
+
+        let mut batch = DBTransaction::new();
+        bc.insert_pending_transition(
+            &mut batch,
+            b1_hash,
+            PendingTransition {
+                proof: vec![1, 2, 3],
+            },
+        );
+        db.write(batch).unwrap();
+
+        assert!(bc.has_pending_transition(&b1_hash));
+        assert!(!bc.has_pending_transition(&genesis.last().hash()));
+        assert_eq!(bc.get_pending_transition(b1_hash).unwrap().proof, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn epoch_transitions_in_range() {
+        use engines::EpochTransition;
+
+        let genesis = BlockBuilder::genesis();
+        let generator = BlockGenerator::new(iter::once(genesis.add_blocks(5)));
+
+        let db = new_db();
+        let bc = new_chain(&genesis.last().encoded(), db.clone());
+        let mut batch = DBTransaction::new();
+        for (i, block) in generator.into_iter().enumerate() {
+            bc.insert_block(&mut batch, &block.encoded(), vec![]);
+            bc.insert_epoch_transition(
+                &mut batch,
+                i as u64,
+                EpochTransition {
+                    block_hash: block.hash(),
+                    block_number: i as u64 + 1,
+                    proof: vec![],
+                },
+            );
+            bc.commit();
+        }
+        db.write(batch).unwrap();
+
+        let block_numbers = |from, to| {
+            bc.epoch_transitions_in(from, to)
+                .map(|(_, transition)| transition.block_number)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(block_numbers(2, 4), vec![2, 3, 4]);
+        assert_eq!(block_numbers(0, 1), vec![1]);
+        assert_eq!(block_numbers(4, 100), vec![4, 5]);
+        assert!(block_numbers(6, 10).is_empty());
+        assert!(block_numbers(3, 2).is_empty());
+    }
+
     #[test]
     fn epoch_transitions_iter() {
         use engines::EpochTransition;
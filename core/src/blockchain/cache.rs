@@ -20,6 +20,13 @@
  *
  ******************************************************************************/
 
+#[cfg(any(test, feature = "debug-cache"))]
+use std::collections::HashSet;
+#[cfg(any(test, feature = "debug-cache"))]
+use aion_types::H256;
+#[cfg(any(test, feature = "debug-cache"))]
+use header::BlockNumber;
+
 /// Represents blockchain's in-memory cache size in bytes.
 #[derive(Debug)]
 pub struct CacheSize {
@@ -45,3 +52,23 @@ impl CacheSize {
             + self.block_receipts
     }
 }
+
+/// Keys currently held by each of the blockchain's in-memory caches.
+#[cfg(any(test, feature = "debug-cache"))]
+#[derive(Debug, Default)]
+pub struct CacheKeys {
+    /// Hashes of cached block headers.
+    pub block_headers: HashSet<H256>,
+    /// Hashes of cached block bodies.
+    pub block_bodies: HashSet<H256>,
+    /// Hashes of cached block details.
+    pub block_details: HashSet<H256>,
+    /// Numbers of cached canonical block hashes.
+    pub block_hashes: HashSet<BlockNumber>,
+    /// Hashes of transactions with cached addresses.
+    pub transaction_addresses: HashSet<H256>,
+    /// Number of cached bloom groups.
+    pub blocks_blooms: usize,
+    /// Hashes of blocks with cached receipts.
+    pub block_receipts: HashSet<H256>,
+}
@@ -22,6 +22,20 @@
 
 //! Blockchain configuration.
 
+use bytes::Bytes;
+use rlp_compress::MAX_DICTIONARY_LEN;
+use unexpected::OutOfBounds;
+use error::Error;
+
+/// Compression applied to block headers and bodies stored in the database.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompressionMode {
+    /// Swap common RLP items using the blocks dictionary.
+    Blocks,
+    /// Store raw RLP.
+    None,
+}
+
 /// Blockchain configuration.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Config {
@@ -29,6 +43,25 @@ pub struct Config {
     pub pref_cache_size: usize,
     /// Maximum cache size in bytes.
     pub max_cache_size: usize,
+    /// Number of blocks scanned in parallel by a single step of a logs query.
+    pub log_scan_chunk_size: usize,
+    /// Whether to check that receipt blooms match their logs on block insertion.
+    pub verify_receipt_blooms: bool,
+    /// Whether to check that the header's receipts root matches the receipts on block insertion.
+    pub verify_receipts_root: bool,
+    /// Whether to check that the header's transactions root matches the transactions on block
+    /// insertion.
+    pub verify_transactions_root: bool,
+    /// Compression of newly stored block headers and bodies.
+    pub compression: CompressionMode,
+    /// Common RLPs swapped out of stored headers and bodies instead of the built-in dictionary,
+    /// up to 126 of them. A database must always be opened with the dictionary it was created with.
+    pub compression_dictionary: Option<Vec<Bytes>>,
+    /// Whether header and body reads of the best block may be served from the in-memory
+    /// best block instead of the cache or database.
+    pub best_block_fast_path: bool,
+    /// Number of most recent canonical blocks whose receipts are never pruned.
+    pub receipts_retained_depth: u64,
 }
 
 impl Default for Config {
@@ -36,6 +69,30 @@ impl Default for Config {
         Config {
             pref_cache_size: 1 << 14,
             max_cache_size: 1 << 20,
+            log_scan_chunk_size: 128,
+            verify_receipt_blooms: false,
+            verify_receipts_root: false,
+            verify_transactions_root: false,
+            compression: CompressionMode::Blocks,
+            compression_dictionary: None,
+            best_block_fast_path: true,
+            receipts_retained_depth: 1024,
+        }
+    }
+}
+
+impl Config {
+    /// Check that the configuration can be used to open a blockchain.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(ref dictionary) = self.compression_dictionary {
+            if dictionary.len() > MAX_DICTIONARY_LEN {
+                return Err(Error::DictionaryTooLarge(OutOfBounds {
+                    min: None,
+                    max: Some(MAX_DICTIONARY_LEN),
+                    found: dictionary.len(),
+                }));
+            }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,420 @@
+/*******************************************************************************
+ * Copyright (c) 2015-2018 Parity Technologies (UK) Ltd.
+ * Copyright (c) 2018-2019 Aion foundation.
+ *
+ *     This file is part of the aion network project.
+ *
+ *     The aion network project is free software: you can redistribute it
+ *     and/or modify it under the terms of the GNU General Public License
+ *     as published by the Free Software Foundation, either version 3 of
+ *     the License, or any later version.
+ *
+ *     The aion network project is distributed in the hope that it will
+ *     be useful, but WITHOUT ANY WARRANTY; without even the implied
+ *     warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ *     See the GNU General Public License for more details.
+ *
+ *     You should have received a copy of the GNU General Public License
+ *     along with the aion network project source files.
+ *     If not, see <https://www.gnu.org/licenses/>.
+ *
+ ******************************************************************************/
+//! Format and verification of exported block dumps.
+//!
+//! A dump starts with a header made of `EXPORT_MAGIC`, a version byte and the genesis hash
+//! of the exporting chain, followed by the RLP of every block. It ends with a trailer tag
+//! and a big-endian CRC-32 of the block bytes.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use aion_types::H256;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+use header::BlockNumber;
+use rlp::{DecoderError, PayloadInfo};
+
+/// Magic bytes at the start of every block dump.
+pub const EXPORT_MAGIC: [u8; 4] = [0xae, b'B', b'L', b'K'];
+/// Version of the block dump format.
+pub const EXPORT_VERSION: u8 = 1;
+// Marks the checksum trailer. Blocks are RLP lists, so they never start with it.
+const TRAILER_TAG: u8 = 0x00;
+// Upper bound of a block read from a stream, so that a corrupt length can't exhaust memory.
+const MAX_BLOCK_LEN: usize = 64 * 1024 * 1024;
+
+/// Summary of a verified block dump.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExportSummary {
+    /// Number of the first block in the dump.
+    pub first: BlockNumber,
+    /// Number of the last block in the dump.
+    pub last: BlockNumber,
+    /// Number of blocks in the dump.
+    pub count: usize,
+}
+
+/// Error found while verifying a block dump.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The stream could not be read or ended in the middle of a block.
+    Io(io::Error),
+    /// The block at given position in the stream is not valid RLP of a block.
+    Decode(usize, DecoderError),
+    /// The parent of the block at given position is not the previous block of the stream.
+    Discontinuity {
+        /// Position of the block in the stream.
+        index: usize,
+        /// Hash of the previous block.
+        expected: H256,
+        /// Parent hash of the block.
+        found: H256,
+    },
+    /// The parent of the first block is not known to the chain.
+    UnknownParent(H256),
+    /// The stream doesn't contain any block.
+    Empty,
+    /// The stream doesn't start with `EXPORT_MAGIC`.
+    BadMagic,
+    /// The dump was written with an unknown format version.
+    UnsupportedVersion(u8),
+    /// The dump was exported from a chain with a different genesis.
+    GenesisMismatch {
+        /// Genesis hash of the local chain.
+        expected: H256,
+        /// Genesis hash recorded in the dump.
+        found: H256,
+    },
+    /// The stream ends without the checksum trailer.
+    MissingChecksum,
+    /// The checksum of the blocks doesn't match the one recorded in the dump.
+    ChecksumMismatch {
+        /// Checksum recorded in the dump.
+        expected: u32,
+        /// Checksum of the blocks read.
+        found: u32,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportError::Io(ref e) => write!(f, "Error reading block stream: {}", e),
+            ExportError::Decode(index, ref e) => {
+                write!(f, "Invalid block #{} in stream: {}", index, e)
+            }
+            ExportError::Discontinuity {
+                index,
+                ref expected,
+                ref found,
+            } => {
+                write!(
+                    f,
+                    "Block #{} in stream has parent {}, expected {}",
+                    index, found, expected
+                )
+            }
+            ExportError::UnknownParent(ref hash) => {
+                write!(f, "Parent {} of the first block is unknown", hash)
+            }
+            ExportError::Empty => write!(f, "Block stream is empty"),
+            ExportError::BadMagic => write!(f, "Block stream is not a block dump"),
+            ExportError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported block dump version {}", version)
+            }
+            ExportError::GenesisMismatch {
+                ref expected,
+                ref found,
+            } => {
+                write!(
+                    f,
+                    "Block dump is from chain with genesis {}, expected {}",
+                    found, expected
+                )
+            }
+            ExportError::MissingChecksum => write!(f, "Block stream ends without a checksum"),
+            ExportError::ChecksumMismatch {
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "Block stream checksum is {:08x}, expected {:08x}",
+                    found, expected
+                )
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self { ExportError::Io(err) }
+}
+
+/// CRC-32 (IEEE) of the block bytes of a dump.
+#[derive(Debug, Clone)]
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self { Crc32(0xffff_ffff) }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 { !self.0 }
+}
+
+/// Writes a block dump: the header on creation, then blocks, then the checksum trailer
+/// on `finish`.
+pub struct ExportWriter<W> {
+    out: W,
+    crc: Crc32,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Write the dump header for a chain with given genesis hash.
+    pub fn new(mut out: W, genesis: &H256) -> io::Result<Self> {
+        out.write_all(&EXPORT_MAGIC)?;
+        out.write_all(&[EXPORT_VERSION])?;
+        out.write_all(&**genesis)?;
+        Ok(ExportWriter {
+            out: out,
+            crc: Crc32::new(),
+        })
+    }
+
+    /// Append the RLP of a block.
+    pub fn write_block(&mut self, block: &[u8]) -> io::Result<()> {
+        self.crc.update(block);
+        self.out.write_all(block)
+    }
+
+    /// Write the checksum trailer and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[TRAILER_TAG])?;
+        self.out.write_u32::<BigEndian>(self.crc.finish())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads the blocks of a dump, checking its header and checksum.
+pub struct ExportReader<R> {
+    input: R,
+    crc: Crc32,
+    index: usize,
+    finished: bool,
+}
+
+impl<R: Read> ExportReader<R> {
+    /// Read the dump header, rejecting dumps of a chain with a different genesis.
+    pub fn new(mut input: R, genesis: &H256) -> Result<Self, ExportError> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if magic != EXPORT_MAGIC {
+            return Err(ExportError::BadMagic);
+        }
+        let version = input.read_u8()?;
+        if version != EXPORT_VERSION {
+            return Err(ExportError::UnsupportedVersion(version));
+        }
+        let mut found = H256::new();
+        input.read_exact(&mut *found)?;
+        if found != *genesis {
+            return Err(ExportError::GenesisMismatch {
+                expected: *genesis,
+                found: found,
+            });
+        }
+        Ok(ExportReader {
+            input: input,
+            crc: Crc32::new(),
+            index: 0,
+            finished: false,
+        })
+    }
+
+    /// Read the next block, returning `None` once the trailer is reached and the checksum
+    /// verified.
+    pub fn next_block(&mut self) -> Result<Option<Bytes>, ExportError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let prefix = match read_byte(&mut self.input)? {
+            Some(prefix) => prefix,
+            None => return Err(ExportError::MissingChecksum),
+        };
+        if prefix == TRAILER_TAG {
+            let expected = self.input.read_u32::<BigEndian>()?;
+            let found = self.crc.finish();
+            if expected != found {
+                return Err(ExportError::ChecksumMismatch {
+                    expected: expected,
+                    found: found,
+                });
+            }
+            self.finished = true;
+            return Ok(None);
+        }
+
+        let bytes = read_rlp_list(&mut self.input, prefix, self.index)?;
+        self.crc.update(&bytes);
+        self.index += 1;
+        Ok(Some(bytes))
+    }
+}
+
+fn read_byte<R: Read>(input: &mut R) -> Result<Option<u8>, ExportError> {
+    let mut byte = [0u8; 1];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Read the next block RLP from a stream of concatenated blocks without any header or
+/// checksum, as written by releases predating the dump header. `index` is the position of
+/// the block in the stream, used in errors. Returns `None` at the end of the stream.
+pub fn read_raw_block<R: Read>(input: &mut R, index: usize) -> Result<Option<Bytes>, ExportError> {
+    match read_byte(input)? {
+        Some(prefix) => read_rlp_list(input, prefix, index).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Read the rest of the RLP list starting with `prefix` from `input`.
+/// The list header carries the length, so no separate framing is needed.
+fn read_rlp_list<R: Read>(input: &mut R, prefix: u8, index: usize) -> Result<Bytes, ExportError> {
+    let mut bytes = vec![prefix];
+    if prefix < 0xc0 {
+        return Err(ExportError::Decode(index, DecoderError::RlpExpectedToBeList));
+    }
+    let len_of_len = if prefix > 0xf7 {
+        (prefix - 0xf7) as usize
+    } else {
+        0
+    };
+    bytes.resize(1 + len_of_len, 0);
+    input.read_exact(&mut bytes[1..])?;
+
+    let payload = PayloadInfo::from(&bytes).map_err(|e| ExportError::Decode(index, e))?;
+    if payload.value_len > MAX_BLOCK_LEN {
+        return Err(ExportError::Decode(index, DecoderError::RlpIsTooBig));
+    }
+    let total = payload.total();
+    let header_len = bytes.len();
+    bytes.resize(total, 0);
+    input.read_exact(&mut bytes[header_len..])?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn checksum_mismatch_is_detected() {
+        let genesis = H256::from(1);
+        let mut writer = ExportWriter::new(Vec::new(), &genesis).unwrap();
+        writer.write_block(&[0xc2, 0x80, 0x80]).unwrap();
+        let mut dump = writer.finish().unwrap();
+
+        let mut reader = ExportReader::new(&dump[..], &genesis).unwrap();
+        assert_eq!(reader.next_block().unwrap(), Some(vec![0xc2, 0x80, 0x80]));
+        assert_eq!(reader.next_block().unwrap(), None);
+
+        // flip a bit of the block
+        let last_block_byte = EXPORT_MAGIC.len() + 1 + 32 + 2;
+        dump[last_block_byte] ^= 0x01;
+        let mut reader = ExportReader::new(&dump[..], &genesis).unwrap();
+        reader.next_block().unwrap();
+        match reader.next_block() {
+            Err(ExportError::ChecksumMismatch {
+                ..
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn header_is_checked() {
+        let genesis = H256::from(1);
+        let dump = ExportWriter::new(Vec::new(), &genesis)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        match ExportReader::new(&dump[..], &H256::from(2)) {
+            Err(ExportError::GenesisMismatch {
+                expected,
+                found,
+            }) => {
+                assert_eq!(expected, H256::from(2));
+                assert_eq!(found, genesis);
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match ExportReader::new(&dump[1..], &genesis) {
+            Err(ExportError::BadMagic) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        let mut newer = dump.clone();
+        newer[EXPORT_MAGIC.len()] = EXPORT_VERSION + 1;
+        match ExportReader::new(&newer[..], &genesis) {
+            Err(ExportError::UnsupportedVersion(_)) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        // truncated before the trailer
+        let mut reader = ExportReader::new(&dump[..dump.len() - 5], &genesis).unwrap();
+        match reader.next_block() {
+            Err(ExportError::MissingChecksum) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn raw_blocks_are_read() {
+        let long = vec![0x01u8; 56];
+        let mut stream = vec![0xc2, 0x01, 0x02, 0xf8, 56];
+        stream.extend_from_slice(&long);
+        stream.push(0x80);
+        let mut stream = &stream[..];
+
+        assert_eq!(read_raw_block(&mut stream, 0).unwrap(), Some(vec![0xc2, 0x01, 0x02]));
+        let mut block = vec![0xf8, 56];
+        block.extend_from_slice(&long);
+        assert_eq!(read_raw_block(&mut stream, 1).unwrap(), Some(block));
+        // only lists are blocks
+        match read_raw_block(&mut stream, 2) {
+            Err(ExportError::Decode(2, DecoderError::RlpExpectedToBeList)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(read_raw_block(&mut stream, 3).unwrap(), None);
+
+        // a corrupt length is refused instead of allocated
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        match read_raw_block(&mut &huge[..], 0) {
+            Err(ExportError::Decode(0, DecoderError::RlpIsTooBig)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
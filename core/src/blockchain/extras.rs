@@ -53,6 +53,53 @@ pub enum ExtrasIndex {
     PendingEpochTransition = 6,
 }
 
+/// Reserved keys of the extras column which hold single chain markers rather than
+/// per-block data. The byte values are part of the database format and must not change.
+#[derive(Copy, Debug, Hash, Eq, PartialEq, Clone)]
+pub enum ExtraKey {
+    /// Hash of the best block
+    Best,
+    /// Hash of the last block of the last block sequence, present while there is a gap
+    First,
+    /// Hash of the best block of the first block sequence, present while there is a gap
+    Ancient,
+    /// `BestBlockHint` of the best block, saving the read of its details on restart
+    BestHint,
+    /// Fingerprint of the custom compression dictionary, absent for the built-in one
+    Dictionary,
+}
+
+impl ExtraKey {
+    /// Raw key the marker is stored under
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match *self {
+            ExtraKey::Best => b"best",
+            ExtraKey::First => b"first",
+            ExtraKey::Ancient => b"ancient",
+            ExtraKey::BestHint => b"besthint",
+            ExtraKey::Dictionary => b"dictionary",
+        }
+    }
+
+    /// Whether `key` could collide with data the chain stores in the extras column: per-block
+    /// data, whose keys start with an `ExtrasIndex` byte, or one of the markers above.
+    pub fn is_reserved(key: &[u8]) -> bool {
+        // every marker must be listed here
+        let markers = [
+            ExtraKey::Best,
+            ExtraKey::First,
+            ExtraKey::Ancient,
+            ExtraKey::BestHint,
+            ExtraKey::Dictionary,
+        ];
+        match key.first() {
+            None => true,
+            Some(&index) if index <= ExtrasIndex::PendingEpochTransition as u8 => true,
+            Some(_) => markers.iter().any(|marker| key.starts_with(marker.as_bytes())),
+        }
+    }
+}
+
 fn with_index(hash: &H256, i: ExtrasIndex) -> H264 {
     let mut result = H264::default();
     result[0] = i as u8;
@@ -188,6 +235,18 @@ impl HeapSizeOf for BlockDetails {
     fn heap_size_of_children(&self) -> usize { self.children.heap_size_of_children() }
 }
 
+/// Number and total difficulty of the best block, stored next to its hash so that a restart
+/// doesn't need to read its details
+#[derive(Debug, PartialEq, Clone, RlpEncodable, RlpDecodable)]
+pub struct BestBlockHint {
+    /// Best block hash, the hint is only valid while it matches the stored best block
+    pub hash: H256,
+    /// Best block number
+    pub number: BlockNumber,
+    /// Total difficulty of the best block
+    pub total_difficulty: U256,
+}
+
 /// Represents address of certain transaction within block
 #[derive(Debug, PartialEq, Clone, RlpEncodable, RlpDecodable)]
 pub struct TransactionAddress {
@@ -229,7 +288,7 @@ pub struct EpochTransitions {
 #[cfg(test)]
 mod tests {
     use rlp::*;
-    use super::BlockReceipts;
+    use super::{BlockReceipts, ExtraKey};
 
     #[test]
     fn encode_block_receipts() {
@@ -242,4 +301,14 @@ mod tests {
         assert!(s.is_finished(), "List should be finished now");
         s.out();
     }
+
+    #[test]
+    fn extra_keys_are_stable() {
+        // changing these breaks existing databases
+        assert_eq!(ExtraKey::Best.as_bytes(), b"best");
+        assert_eq!(ExtraKey::First.as_bytes(), b"first");
+        assert_eq!(ExtraKey::Ancient.as_bytes(), b"ancient");
+        assert_eq!(ExtraKey::BestHint.as_bytes(), b"besthint");
+        assert_eq!(ExtraKey::Dictionary.as_bytes(), b"dictionary");
+    }
 }
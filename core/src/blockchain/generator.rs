@@ -23,7 +23,7 @@
 //! Blockchain generator for tests.
 
 use std::collections::VecDeque;
-use aion_types::{U256, H256};
+use aion_types::{Address, U256, H256};
 use ethbloom::Bloom;
 
 use bytes::Bytes;
@@ -59,6 +59,7 @@ impl Block {
 #[derive(Debug)]
 pub struct BlockOptions {
     pub difficulty: U256,
+    pub author: Address,
     pub bloom: Bloom,
     pub transactions: Vec<SignedTransaction>,
 }
@@ -67,6 +68,7 @@ impl Default for BlockOptions {
     fn default() -> Self {
         BlockOptions {
             difficulty: 10.into(),
+            author: Address::default(),
             bloom: Bloom::default(),
             transactions: Vec::new(),
         }
@@ -150,6 +152,7 @@ impl BlockBuilder {
             block.header.set_number(block_number);
             block.header.set_log_bloom(metadata.bloom);
             block.header.set_difficulty(metadata.difficulty);
+            block.header.set_author(metadata.author);
             block.transactions = metadata.transactions;
 
             parent_hash = block.hash();
@@ -27,6 +27,7 @@ mod block_info;
 mod blockchain;
 mod cache;
 mod config;
+mod export;
 mod extras;
 mod import_route;
 mod update;
@@ -34,9 +35,17 @@ mod update;
 #[cfg(test)]
 pub mod generator;
 
-pub use self::blockchain::{BlockProvider, BlockChain};
+pub use self::blockchain::{
+    bloom_for_logs, receipts_root, transactions_root, BlockProvider, BlockChain,
+};
 pub use self::cache::CacheSize;
-pub use self::config::Config;
-pub use self::extras::{BlockReceipts, BlockDetails, TransactionAddress};
+#[cfg(any(test, feature = "debug-cache"))]
+pub use self::cache::CacheKeys;
+pub use self::config::{Config, CompressionMode};
+pub use self::export::{
+    read_raw_block, ExportSummary, ExportError, ExportReader, ExportWriter, EXPORT_MAGIC,
+    EXPORT_VERSION,
+};
+pub use self::extras::{BlockReceipts, BlockDetails, ExtraKey, TransactionAddress};
 pub use self::import_route::ImportRoute;
 pub use types::tree_route::TreeRoute;
@@ -54,6 +54,9 @@ where T: Eq + Hash
         }
     }
 
+    /// Size above which the cache is shrunk on garbage collection.
+    pub fn max_cache_size(&self) -> usize { self.max_cache_size }
+
     pub fn note_used(&mut self, id: T) {
         if !self.cache_usage[0].contains(&id) {
             if let Some(c) = self
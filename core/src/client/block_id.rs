@@ -0,0 +1,115 @@
+/*******************************************************************************
+ * Copyright (c) 2015-2018 Parity Technologies (UK) Ltd.
+ * Copyright (c) 2018-2019 Aion foundation.
+ *
+ *     This file is part of the aion network project.
+ *
+ *     The aion network project is free software: you can redistribute it
+ *     and/or modify it under the terms of the GNU General Public License
+ *     as published by the Free Software Foundation, either version 3 of
+ *     the License, or any later version.
+ *
+ *     The aion network project is distributed in the hope that it will
+ *     be useful, but WITHOUT ANY WARRANTY; without even the implied
+ *     warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ *     See the GNU General Public License for more details.
+ *
+ *     You should have received a copy of the GNU General Public License
+ *     along with the aion network project source files.
+ *     If not, see <https://www.gnu.org/licenses/>.
+ *
+ ******************************************************************************/
+
+//! Resolution of `BlockId`s to block hashes.
+
+use aion_types::H256;
+use header::BlockNumber;
+use types::ids::BlockId;
+
+/// Resolve `id` to a block hash, given lookups of the best block, canonical blocks by number
+/// and the pending block. The genesis block is looked up as number 0.
+///
+/// Shared by the client and the test client so that both resolve ids the same way.
+pub fn resolve_block_id<B, N, P>(id: BlockId, best: B, number: N, pending: P) -> Option<H256>
+where
+    B: FnOnce() -> Option<H256>,
+    N: FnOnce(BlockNumber) -> Option<H256>,
+    P: FnOnce() -> Option<H256>,
+{
+    match id {
+        BlockId::Hash(hash) => Some(hash),
+        BlockId::Number(n) => number(n),
+        BlockId::Earliest => number(0),
+        BlockId::Latest => best(),
+        BlockId::Pending => pending(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use aion_types::H256;
+    use client::{BlockChainClient, EachBlockWith, TestBlockChainClient};
+    use spec::Spec;
+    use tests::helpers::generate_dummy_client;
+    use types::ids::BlockId;
+    use super::resolve_block_id;
+
+    #[test]
+    fn resolves_every_kind_of_id() {
+        let numbers: HashMap<u64, H256> = (0..3).map(|n| (n, H256::from(n + 1))).collect();
+        let resolve = |id| {
+            resolve_block_id(
+                id,
+                || numbers.get(&2).cloned(),
+                |n| numbers.get(&n).cloned(),
+                || Some(H256::from(42)),
+            )
+        };
+
+        assert_eq!(resolve(BlockId::Hash(H256::from(7))), Some(H256::from(7)));
+        assert_eq!(resolve(BlockId::Number(1)), Some(H256::from(2)));
+        assert_eq!(resolve(BlockId::Number(3)), None);
+        assert_eq!(resolve(BlockId::Earliest), Some(H256::from(1)));
+        assert_eq!(resolve(BlockId::Latest), Some(H256::from(3)));
+        assert_eq!(resolve(BlockId::Pending), Some(H256::from(42)));
+    }
+
+    // number of the canonical block of `client` which `id` resolves to
+    fn canonical_number<C: BlockChainClient>(client: &C, id: BlockId) -> Option<u64> {
+        let hash = client.block_hash(id)?;
+        (0..5).find(|n| client.block_hash(BlockId::Number(*n)) == Some(hash))
+    }
+
+    #[test]
+    fn client_and_test_client_agree() {
+        let client = generate_dummy_client(3);
+        let test_client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        test_client.add_blocks(3, EachBlockWith::Nothing);
+
+        // `Pending` is left out: the test client has no miner and resolves it to the best block
+        let ids = vec![
+            BlockId::Earliest,
+            BlockId::Number(0),
+            BlockId::Number(2),
+            BlockId::Number(3),
+            BlockId::Number(4),
+            BlockId::Latest,
+        ];
+        for id in ids {
+            assert_eq!(
+                canonical_number(&*client, id),
+                canonical_number(&test_client, id),
+                "{:?}",
+                id
+            );
+        }
+        assert_eq!(canonical_number(&*client, BlockId::Latest), Some(3));
+        assert_eq!(canonical_number(&*client, BlockId::Number(4)), None);
+
+        let hash = client.block_hash(BlockId::Number(2)).unwrap();
+        let test_hash = test_client.block_hash(BlockId::Number(2)).unwrap();
+        assert_eq!(client.block_hash(BlockId::Hash(hash)), Some(hash));
+        assert_eq!(test_client.block_hash(BlockId::Hash(test_hash)), Some(test_hash));
+    }
+}
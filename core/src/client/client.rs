@@ -39,15 +39,18 @@ use util_error::UtilError;
 // other
 use aion_types::{Address, H128, H256, H264, U256};
 use block::*;
-use blockchain::{BlockChain, BlockProvider, ImportRoute, TransactionAddress, TreeRoute};
+use blockchain::{
+    BlockChain, BlockProvider, ExtraKey, ImportRoute, TransactionAddress, TreeRoute,
+};
 use client::ancient_import::AncientVerifier;
 use client::Error as ClientError;
 use client::{
     BlockChainClient, BlockId, BlockImportError, CallAnalytics, ChainNotify, ClientConfig,
-    MiningBlockChainClient, ProvingBlockChainClient, PruningInfo, TransactionId,
+    MiningBlockChainClient, ProvingBlockChainClient, PruningInfo, TransactionId, resolve_block_id,
 };
 use encoded;
 use engines::{EpochTransition, EthEngine};
+use ethbloom::Bloom;
 use error::{BlockError, CallError, ExecutionError, ImportError, ImportResult};
 use executive::{contract_address, Executed, Executive, BatchResult};
 use factory::{Factories, VmFactory};
@@ -184,7 +187,11 @@ impl Client {
         }
 
         let gb = spec.genesis_block();
-        let chain = Arc::new(BlockChain::new(config.blockchain.clone(), &gb, db.clone()));
+        let chain = Arc::new(BlockChain::new_checked(
+            config.blockchain.clone(),
+            &gb,
+            db.clone(),
+        )?);
 
         trace!(
             target: "client",
@@ -995,17 +1002,16 @@ impl Client {
     pub fn pruning_history(&self) -> u64 { self.history }
 
     fn block_hash(chain: &BlockChain, miner: &Miner, id: BlockId) -> Option<H256> {
-        match id {
-            BlockId::Hash(hash) => Some(hash),
-            BlockId::Number(number) => chain.block_hash(number),
-            BlockId::Earliest => chain.block_hash(0),
-            BlockId::Latest => Some(chain.best_block_hash()),
-            BlockId::Pending => {
+        resolve_block_id(
+            id,
+            || Some(chain.best_block_hash()),
+            |number| chain.block_hash(number),
+            || {
                 miner
                     .pending_block_header(chain.best_block_number())
                     .map(|header| header.hash())
-            }
-        }
+            },
+        )
     }
 
     fn transaction_address(&self, id: TransactionId) -> Option<TransactionAddress> {
@@ -1226,7 +1232,7 @@ impl Client {
         let new_best_hash = self
             .block_hash(BlockId::Number(new_block))
             .expect("can not found block , db may crashed");
-        batch.put(::db::COL_EXTRA, b"best", &new_best_hash);
+        batch.put(::db::COL_EXTRA, ExtraKey::Best.as_bytes(), &new_best_hash);
         // reset state
         let latest_era_key = [b'l', b'a', b's', b't', 0, 0, 0, 0, 0, 0, 0, 0];
         batch.put(::db::COL_STATE, &latest_era_key, &encode(&new_block));
@@ -1396,6 +1402,8 @@ impl BlockChainClient for Client {
 
     fn spec_name(&self) -> String { self.config.spec_name.clone() }
 
+    fn chain_id(&self) -> Option<u64> { self.engine.params().chain_id }
+
     fn best_block_header(&self) -> encoded::Header { self.chain.read().best_block_header() }
 
     fn block_header(&self, id: BlockId) -> Option<::encoded::Header> {
@@ -1490,6 +1498,10 @@ impl BlockChainClient for Client {
             .and_then(|x| x)
     }
 
+    fn state_root(&self, id: BlockId) -> Option<H256> {
+        self.block_header(id).map(|header| header.state_root())
+    }
+
     fn block_hash(&self, id: BlockId) -> Option<H256> {
         let chain = self.chain.read();
         Self::block_hash(&chain, &self.miner, id)
@@ -1672,6 +1684,16 @@ impl BlockChainClient for Client {
         })
     }
 
+    fn executed_to_receipt(
+        &self,
+        id: TransactionId,
+        executed: &Executed,
+    ) -> Option<LocalizedReceipt>
+    {
+        self.transaction(id)
+            .map(|transaction| executed_receipt(transaction, executed))
+    }
+
     fn tree_route(&self, from: &H256, to: &H256) -> Option<TreeRoute> {
         let chain = self.chain.read();
         match chain.is_known(from) && chain.is_known(to) {
@@ -2053,7 +2075,7 @@ impl Drop for Client {
 
 /// Returns `LocalizedReceipt` given `LocalizedTransaction`
 /// and a vector of receipts from given block up to transaction index.
-fn transaction_receipt(
+pub(crate) fn transaction_receipt(
     mut tx: LocalizedTransaction,
     mut receipts: Vec<Receipt>,
 ) -> LocalizedReceipt
@@ -2117,6 +2139,63 @@ fn transaction_receipt(
     }
 }
 
+/// Localize a (simulated) execution result of the given transaction as a receipt.
+/// Log indexes are relative to the transaction, since prior receipts of the block are unknown.
+pub(crate) fn executed_receipt(
+    mut tx: LocalizedTransaction,
+    executed: &Executed,
+) -> LocalizedReceipt
+{
+    let sender = tx.sender();
+    let transaction_hash = tx.hash();
+    let block_hash = tx.block_hash;
+    let block_number = tx.block_number;
+    let transaction_index = tx.transaction_index;
+
+    LocalizedReceipt {
+        transaction_hash: transaction_hash,
+        transaction_index: transaction_index,
+        block_hash: block_hash,
+        block_number: block_number,
+        cumulative_gas_used: executed.cumulative_gas_used,
+        gas_used: executed.gas_used,
+        contract_address: match tx.action {
+            Action::Call(_) => None,
+            Action::Create => Some(contract_address(&sender, &tx.nonce).0),
+        },
+        logs: executed
+            .logs
+            .iter()
+            .enumerate()
+            .map(|(i, log)| {
+                LocalizedLogEntry {
+                    entry: log.clone(),
+                    block_hash: block_hash,
+                    block_number: block_number,
+                    transaction_hash: transaction_hash,
+                    transaction_index: transaction_index,
+                    transaction_log_index: i,
+                    log_index: i,
+                }
+            })
+            .collect(),
+        log_bloom: executed
+            .logs
+            .iter()
+            .fold(Bloom::default(), |b, l| &b | &l.bloom()),
+        state_root: executed.state_root,
+        from: Some(sender),
+        to: match tx.action {
+            Action::Create => None,
+            Action::Call(ref address) => Some(address.clone().into()),
+        },
+        gas_price: tx.gas_price,
+        gas_limit: tx.gas,
+        output: executed.output.clone(),
+        error_message: executed.exception.clone(),
+    }
+}
+
 /*#[cfg(test)]
 mod tests {
 
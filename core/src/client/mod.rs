@@ -23,11 +23,13 @@
 //! Blockchain database client.
 
 mod ancient_import;
+mod block_id;
 mod config;
 mod error;
 mod test_client;
 mod client;
 
+pub use self::block_id::resolve_block_id;
 pub use self::client::*;
 pub use self::config::{ClientConfig, DatabaseCompactionProfile, BlockChainConfig, VMType};
 pub use self::error::Error;
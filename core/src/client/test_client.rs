@@ -46,8 +46,9 @@ use transaction::{
 use blockchain::{TreeRoute, BlockReceipts};
 use client::{
     BlockChainClient, MiningBlockChainClient, BlockChainInfo, BlockStatus, BlockId, TransactionId,
-    LastHashes, CallAnalytics, BlockImportError, ProvingBlockChainClient,
+    LastHashes, CallAnalytics, BlockImportError, ProvingBlockChainClient, resolve_block_id,
 };
+use client::client::{executed_receipt, transaction_receipt};
 use db::{COL_STATE, DB_NAMES};
 use header::{Header as BlockHeader, BlockNumber};
 use filter::Filter;
@@ -66,6 +67,7 @@ use executive::Executed;
 use error::CallError;
 use state_db::StateDB;
 use encoded;
+use views::BlockView;
 use kvdb::{KeyValueDB, MemoryDBRepository};
 
 use super::super::transaction::UnverifiedTransaction;
@@ -84,6 +86,8 @@ pub struct TestBlockChainClient {
     pub extra_data: Bytes,
     /// Difficulty.
     pub difficulty: RwLock<U256>,
+    /// Total difficulty of each imported block.
+    pub total_difficulties: RwLock<HashMap<H256, U256>>,
     /// Balances.
     pub balances: RwLock<HashMap<Address, U256>>,
     /// Nonces.
@@ -92,10 +96,22 @@ pub struct TestBlockChainClient {
     pub storage: RwLock<HashMap<(Address, H128), H128>>,
     /// Code.
     pub code: RwLock<HashMap<Address, Bytes>>,
+    /// Balances in the pending state, overriding `balances`.
+    pub pending_balances: RwLock<HashMap<Address, U256>>,
+    /// Nonces in the pending state, overriding `nonces`.
+    pub pending_nonces: RwLock<HashMap<Address, U256>>,
+    /// Storage in the pending state, overriding `storage`.
+    pub pending_storage: RwLock<HashMap<(Address, H128), H128>>,
+    /// Code in the pending state, overriding `code`.
+    pub pending_code: RwLock<HashMap<Address, Bytes>>,
     /// Execution result.
     pub execution_result: RwLock<Option<Result<Executed, CallError>>>,
+    /// Per-transaction execution results replayed for a block, in transaction order.
+    pub block_execution_results: RwLock<HashMap<H256, Vec<Executed>>>,
     /// Transaction receipts.
     pub receipts: RwLock<HashMap<TransactionId, LocalizedReceipt>>,
+    /// Raw receipts of blocks imported with receipts.
+    pub block_receipts: RwLock<HashMap<H256, Bytes>>,
     /// Logs
     pub logs: RwLock<Vec<LocalizedLogEntry>>,
     /// Block queue size.
@@ -149,12 +165,19 @@ impl TestBlockChainClient {
             extra_data: extra_data,
             last_hash: RwLock::new(H256::new()),
             difficulty: RwLock::new(spec.genesis_header().difficulty().clone()),
+            total_difficulties: RwLock::new(HashMap::new()),
             balances: RwLock::new(HashMap::new()),
             nonces: RwLock::new(HashMap::new()),
             storage: RwLock::new(HashMap::new()),
             code: RwLock::new(HashMap::new()),
+            pending_balances: RwLock::new(HashMap::new()),
+            pending_nonces: RwLock::new(HashMap::new()),
+            pending_storage: RwLock::new(HashMap::new()),
+            pending_code: RwLock::new(HashMap::new()),
             execution_result: RwLock::new(None),
+            block_execution_results: RwLock::new(HashMap::new()),
             receipts: RwLock::new(HashMap::new()),
+            block_receipts: RwLock::new(HashMap::new()),
             logs: RwLock::new(Vec::new()),
             queue_size: AtomicUsize::new(0),
             miner: Arc::new(Miner::with_spec(&spec)),
@@ -170,6 +193,11 @@ impl TestBlockChainClient {
         // insert genesis hash.
         client.blocks.get_mut().insert(genesis_hash, genesis_block);
         client.numbers.get_mut().insert(0, genesis_hash);
+        let genesis_difficulty = *client.difficulty.get_mut();
+        client
+            .total_difficulties
+            .get_mut()
+            .insert(genesis_hash, genesis_difficulty);
         *client.last_hash.get_mut() = genesis_hash;
         client.genesis_hash = genesis_hash;
         client
@@ -185,6 +213,12 @@ impl TestBlockChainClient {
         *self.execution_result.write() = Some(result);
     }
 
+    /// Set the results replayed for the transactions of block `hash`. Blocks without
+    /// results fall back to the single execution result.
+    pub fn set_block_execution_results(&self, hash: H256, results: Vec<Executed>) {
+        self.block_execution_results.write().insert(hash, results);
+    }
+
     /// Set the balance of account `address` to `balance`.
     pub fn set_balance(&self, address: Address, balance: U256) {
         self.balances.write().insert(address, balance);
@@ -205,6 +239,26 @@ impl TestBlockChainClient {
         self.storage.write().insert((address, position), value);
     }
 
+    /// Set the balance of account `address` in the pending state only.
+    pub fn set_pending_balance(&self, address: Address, balance: U256) {
+        self.pending_balances.write().insert(address, balance);
+    }
+
+    /// Set the nonce of account `address` in the pending state only.
+    pub fn set_pending_nonce(&self, address: Address, nonce: U256) {
+        self.pending_nonces.write().insert(address, nonce);
+    }
+
+    /// Set `code` at `address` in the pending state only.
+    pub fn set_pending_code(&self, address: Address, code: Bytes) {
+        self.pending_code.write().insert(address, code);
+    }
+
+    /// Set storage `position` to `value` for account `address` in the pending state only.
+    pub fn set_pending_storage(&self, address: Address, position: H128, value: H128) {
+        self.pending_storage.write().insert((address, position), value);
+    }
+
     /// Set block queue size for testing
     pub fn set_queue_size(&self, size: usize) { self.queue_size.store(size, AtomicOrder::Relaxed); }
 
@@ -216,10 +270,19 @@ impl TestBlockChainClient {
 
     /// Add blocks to test client.
     pub fn add_blocks(&self, count: usize, with: EachBlockWith) {
+        self.add_blocks_with(count, with, |n| (U256::from(n), 0));
+    }
+
+    /// Add blocks to test client, taking difficulty and timestamp of each block
+    /// from `params` called with the block number.
+    pub fn add_blocks_with<F>(&self, count: usize, with: EachBlockWith, params: F)
+    where F: Fn(BlockNumber) -> (U256, u64) {
         let len = self.numbers.read().len();
         for n in len..(len + count) {
+            let (difficulty, timestamp) = params(n as BlockNumber);
             let mut header = BlockHeader::new();
-            header.set_difficulty(From::from(n));
+            header.set_difficulty(difficulty);
+            header.set_timestamp(timestamp);
             header.set_parent_hash(self.last_hash.read().clone());
             header.set_number(n as BlockNumber);
             header.set_gas_limit(U256::from(1_000_000));
@@ -259,6 +322,23 @@ impl TestBlockChainClient {
         }
     }
 
+    /// Add a block without transactions to test client, sealed by `author` with `extra` data.
+    pub fn add_block_with_author(&self, author: Address, extra: Bytes) {
+        let n = self.numbers.read().len();
+        let mut header = BlockHeader::new();
+        header.set_difficulty(U256::from(n));
+        header.set_parent_hash(self.last_hash.read().clone());
+        header.set_number(n as BlockNumber);
+        header.set_gas_limit(U256::from(1_000_000));
+        header.set_author(author);
+        header.set_extra_data(extra);
+
+        let mut rlp = RlpStream::new_list(2);
+        rlp.append(&header);
+        rlp.append_raw(&::rlp::EMPTY_LIST_RLP, 1);
+        self.import_block(rlp.as_raw().to_vec()).unwrap();
+    }
+
     /// Make a bad block by setting invalid extra data.
     pub fn corrupt_block(&self, n: BlockNumber) {
         let hash = self.block_hash(BlockId::Number(n)).unwrap();
@@ -291,17 +371,14 @@ impl TestBlockChainClient {
     }
 
     fn block_hash(&self, id: BlockId) -> Option<H256> {
-        match id {
-            BlockId::Hash(hash) => Some(hash),
-            BlockId::Number(n) => self.numbers.read().get(&(n as usize)).cloned(),
-            BlockId::Earliest => self.numbers.read().get(&0).cloned(),
-            BlockId::Latest | BlockId::Pending => {
-                self.numbers
-                    .read()
-                    .get(&(self.numbers.read().len() - 1))
-                    .cloned()
-            }
-        }
+        let numbers = self.numbers.read();
+        let latest = || numbers.get(&(numbers.len() - 1)).cloned();
+        resolve_block_id(
+            id,
+            &latest,
+            |n| numbers.get(&(n as usize)).cloned(),
+            &latest,
+        )
     }
 
     /// Inserts a transaction with given gas price to miners transactions queue.
@@ -338,6 +415,39 @@ impl TestBlockChainClient {
 
     /// Set reported history size.
     pub fn set_history(&self, h: Option<u64>) { *self.history.write() = h; }
+
+    /// Whether state queries can be answered at given block. State of canonical blocks below
+    /// the reported history is considered pruned. There is only one state, so it is returned
+    /// for every block which is not pruned.
+    fn state_available(&self, id: BlockId) -> bool {
+        match id {
+            BlockId::Latest | BlockId::Pending => true,
+            BlockId::Number(n) => {
+                let earliest = BlockChainClient::pruning_info(self).earliest_state;
+                let best = BlockChainClient::chain_info(self).best_block_number;
+                n >= earliest && n <= best
+            }
+            _ => false,
+        }
+    }
+
+    /// Find a transaction in the imported blocks.
+    fn find_transaction(&self, id: TransactionId) -> Option<LocalizedTransaction> {
+        match id {
+            TransactionId::Location(block, index) => {
+                self.block(block)
+                    .and_then(|block| block.view().localized_transaction_at(index))
+            }
+            TransactionId::Hash(hash) => {
+                let hashes = self.numbers.read().values().cloned().collect::<Vec<_>>();
+                hashes
+                    .into_iter()
+                    .filter_map(|h| self.block(BlockId::Hash(h)))
+                    .flat_map(|block| block.view().localized_transactions())
+                    .find(|t| t.hash() == hash)
+            }
+        }
+    }
 }
 
 pub fn get_temp_state_db() -> (StateDB, TempDir) {
@@ -443,69 +553,103 @@ impl BlockChainClient for TestBlockChainClient {
 
     fn replay_block_transactions(
         &self,
-        _block: BlockId,
+        block: BlockId,
         _analytics: CallAnalytics,
     ) -> Result<Box<Iterator<Item = Executed>>, CallError>
     {
+        let results = Self::block_hash(self, block)
+            .and_then(|hash| self.block_execution_results.read().get(&hash).cloned());
+        if let Some(results) = results {
+            return Ok(Box::new(results.into_iter()));
+        }
         Ok(Box::new(
             self.execution_result.read().clone().unwrap().into_iter(),
         ))
     }
 
-    fn block_total_difficulty(&self, _id: BlockId) -> Option<U256> { Some(U256::zero()) }
+    fn block_total_difficulty(&self, id: BlockId) -> Option<U256> {
+        Self::block_hash(self, id)
+            .and_then(|hash| self.total_difficulties.read().get(&hash).cloned())
+    }
 
     fn block_hash(&self, id: BlockId) -> Option<H256> { Self::block_hash(self, id) }
 
+    fn canonical_hash(&self, number: BlockNumber) -> Option<H256> {
+        let numbers = self.numbers.read();
+        if number as usize >= numbers.len() {
+            return None;
+        }
+        numbers.get(&(number as usize)).cloned()
+    }
+
     fn nonce(&self, address: &Address, id: BlockId) -> Option<U256> {
-        match id {
-            BlockId::Latest | BlockId::Pending => {
-                Some(
-                    self.nonces
-                        .read()
-                        .get(address)
-                        .cloned()
-                        .unwrap_or(U256::zero()),
-                )
+        if !self.state_available(id) {
+            return None;
+        }
+        if let BlockId::Pending = id {
+            if let Some(nonce) = self.pending_nonces.read().get(address) {
+                return Some(*nonce);
             }
-            _ => None,
         }
+        Some(
+            self.nonces
+                .read()
+                .get(address)
+                .cloned()
+                .unwrap_or(U256::zero()),
+        )
     }
 
     fn storage_root(&self, _address: &Address, _id: BlockId) -> Option<H256> { None }
 
+    fn state_root(&self, id: BlockId) -> Option<H256> {
+        self.block_header(id).map(|header| header.state_root())
+    }
+
     fn latest_nonce(&self, address: &Address) -> U256 {
         self.nonce(address, BlockId::Latest).unwrap()
     }
 
     fn code(&self, address: &Address, id: BlockId) -> Option<Option<Bytes>> {
-        match id {
-            BlockId::Latest | BlockId::Pending => Some(self.code.read().get(address).cloned()),
-            _ => None,
+        if !self.state_available(id) {
+            return None;
+        }
+        if let BlockId::Pending = id {
+            if let Some(code) = self.pending_code.read().get(address) {
+                return Some(Some(code.clone()));
+            }
         }
+        Some(self.code.read().get(address).cloned())
     }
 
     fn code_hash(&self, address: &Address, id: BlockId) -> Option<H256> {
-        match id {
-            BlockId::Latest | BlockId::Pending => {
-                self.code.read().get(address).map(|c| blake2b(&c))
+        if !self.state_available(id) {
+            return None;
+        }
+        if let BlockId::Pending = id {
+            if let Some(code) = self.pending_code.read().get(address) {
+                return Some(blake2b(code));
             }
-            _ => None,
         }
+        self.code.read().get(address).map(|c| blake2b(&c))
     }
 
     fn balance(&self, address: &Address, id: BlockId) -> Option<U256> {
-        match id {
-            BlockId::Latest | BlockId::Pending => {
-                Some(
-                    self.balances
-                        .read()
-                        .get(address)
-                        .cloned()
-                        .unwrap_or_else(U256::zero),
-                )
+        if !self.state_available(id) {
+            return None;
+        }
+        if let BlockId::Pending = id {
+            if let Some(balance) = self.pending_balances.read().get(address) {
+                return Some(*balance);
             }
-            _ => None,
         }
+        Some(
+            self.balances
+                .read()
+                .get(address)
+                .cloned()
+                .unwrap_or_else(U256::zero),
+        )
     }
 
     fn latest_balance(&self, address: &Address) -> U256 {
@@ -513,18 +657,22 @@ impl BlockChainClient for TestBlockChainClient {
     }
 
     fn storage_at(&self, address: &Address, position: &H128, id: BlockId) -> Option<H128> {
-        match id {
-            BlockId::Latest | BlockId::Pending => {
-                Some(
-                    self.storage
-                        .read()
-                        .get(&(address.clone(), position.clone()))
-                        .cloned()
-                        .unwrap_or_else(H128::new),
-                )
+        if !self.state_available(id) {
+            return None;
+        }
+        if let BlockId::Pending = id {
+            let key = (address.clone(), position.clone());
+            if let Some(value) = self.pending_storage.read().get(&key) {
+                return Some(*value);
             }
-            _ => None,
         }
+        Some(
+            self.storage
+                .read()
+                .get(&(address.clone(), position.clone()))
+                .cloned()
+                .unwrap_or_else(H128::new),
+        )
     }
 
     fn list_accounts(
@@ -559,8 +707,20 @@ impl BlockChainClient for TestBlockChainClient {
         self.receipts.read().get(&id).cloned()
     }
 
+    fn executed_to_receipt(
+        &self,
+        id: TransactionId,
+        executed: &Executed,
+    ) -> Option<LocalizedReceipt>
+    {
+        self.find_transaction(id)
+            .map(|transaction| executed_receipt(transaction, executed))
+    }
+
     fn logs(&self, filter: Filter) -> Vec<LocalizedLogEntry> {
         let mut logs = self.logs.read().clone();
+        // match the ordering of the real client
+        logs.sort_by_key(|log| (log.block_number, log.transaction_index, log.log_index));
         let len = logs.len();
         match filter.limit {
             Some(limit) if limit <= len => logs.split_off(len - limit),
@@ -676,6 +836,9 @@ impl BlockChainClient for TestBlockChainClient {
     }
 
     fn block_receipts(&self, hash: &H256) -> Option<Bytes> {
+        if let Some(receipts) = self.block_receipts.read().get(hash) {
+            return Some(receipts.clone());
+        }
         // starts with 'f' ?
         if *hash > H256::from("f000000000000000000000000000000000000000000000000000000000000000") {
             let receipt = BlockReceipts::new(vec![Receipt::new(
@@ -721,6 +884,19 @@ impl BlockChainClient for TestBlockChainClient {
                 }
             }
         }
+        let parent_difficulty = match number {
+            0 => U256::zero(),
+            _ => {
+                self.total_difficulties
+                    .read()
+                    .get(header.parent_hash())
+                    .cloned()
+                    .unwrap_or_default()
+            }
+        };
+        self.total_difficulties
+            .write()
+            .insert(h.clone(), parent_difficulty + header.difficulty().clone());
         let len = self.numbers.read().len();
         if number == len {
             {
@@ -748,8 +924,22 @@ impl BlockChainClient for TestBlockChainClient {
         Ok(h)
     }
 
-    fn import_block_with_receipts(&self, b: Bytes, _r: Bytes) -> Result<H256, BlockImportError> {
-        self.import_block(b)
+    fn import_block_with_receipts(&self, b: Bytes, r: Bytes) -> Result<H256, BlockImportError> {
+        let receipts: Vec<Receipt> = ::rlp::decode_list(&r);
+        let transactions = BlockView::new(&b).localized_transactions();
+        let hash = self.import_block(b)?;
+
+        for transaction in transactions {
+            let index = transaction.transaction_index;
+            if index >= receipts.len() {
+                break;
+            }
+            let id = TransactionId::Hash(transaction.hash());
+            let receipt = transaction_receipt(transaction, receipts[..index + 1].to_vec());
+            self.receipts.write().insert(id, receipt);
+        }
+        self.block_receipts.write().insert(hash, r);
+        Ok(hash)
     }
 
     fn queue_info(&self) -> QueueInfo {
@@ -799,6 +989,8 @@ impl BlockChainClient for TestBlockChainClient {
 
     fn spec_name(&self) -> String { "foundation".into() }
 
+    fn chain_id(&self) -> Option<u64> { self.spec.params().chain_id }
+
     fn disable(&self) {
         unimplemented!();
     }
@@ -811,7 +1003,7 @@ impl BlockChainClient for TestBlockChainClient {
                 .history
                 .read()
                 .as_ref()
-                .map(|x| best_num - x)
+                .map(|x| best_num.saturating_sub(*x))
                 .unwrap_or(0),
         }
     }
@@ -868,3 +1060,334 @@ impl super::traits::EngineClient for TestBlockChainClient {
         BlockChainClient::block_header(self, id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use aion_types::{Address, H128, H256, U256};
+    use bytes::Bytes;
+    use client::{BlockChainClient, BlockId, TransactionId, Executed};
+    use filter::Filter;
+    use log_entry::{LogEntry, LocalizedLogEntry};
+    use receipt::Receipt;
+    use spec::Spec;
+    use super::{TestBlockChainClient, EachBlockWith};
+
+    fn executed() -> Executed {
+        Executed {
+            exception: String::default(),
+            gas: U256::from(100_000),
+            gas_used: U256::from(21_000),
+            refunded: U256::from(79_000),
+            cumulative_gas_used: U256::from(42_000),
+            logs: vec![LogEntry {
+                address: Default::default(),
+                topics: vec![],
+                data: vec![1],
+            }],
+            contracts_created: vec![],
+            output: vec![2],
+            state_diff: None,
+            transaction_fee: U256::zero(),
+            touched: HashSet::new(),
+            state_root: H256::from(3),
+        }
+    }
+
+    #[test]
+    fn should_build_receipt_from_executed() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(1, EachBlockWith::Transaction);
+        let block = client.block(BlockId::Number(1)).unwrap();
+        let transaction = block.view().localized_transaction_at(0).unwrap();
+
+        let executed = executed();
+        let receipt = client
+            .executed_to_receipt(TransactionId::Hash(transaction.hash()), &executed)
+            .unwrap();
+        assert_eq!(receipt.transaction_hash, transaction.hash());
+        assert_eq!(receipt.transaction_index, 0);
+        assert_eq!(receipt.block_hash, block.hash());
+        assert_eq!(receipt.block_number, 1);
+        assert_eq!(receipt.gas_used, executed.gas_used);
+        assert_eq!(receipt.cumulative_gas_used, executed.cumulative_gas_used);
+        assert_eq!(receipt.gas_limit, transaction.gas);
+        assert_eq!(receipt.logs.len(), 1);
+        assert_eq!(receipt.logs[0].entry, executed.logs[0]);
+        assert_eq!(receipt.output, executed.output);
+        assert_eq!(receipt.state_root, executed.state_root);
+        assert!(receipt.contract_address.is_some());
+
+        let by_location = client
+            .executed_to_receipt(TransactionId::Location(BlockId::Number(1), 0), &executed)
+            .unwrap();
+        assert_eq!(by_location, receipt);
+        assert!(client
+            .executed_to_receipt(TransactionId::Hash(H256::from(1)), &executed)
+            .is_none());
+    }
+
+    #[test]
+    fn should_replay_block_execution_results() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(2, EachBlockWith::Transaction);
+        let hash = client.block_hash(BlockId::Number(2)).unwrap();
+
+        let first = executed();
+        let mut second = executed();
+        second.gas_used = U256::from(50_000);
+        second.output = vec![3];
+        client.set_block_execution_results(hash, vec![first.clone(), second.clone()]);
+        client.set_execution_result(Ok(executed()));
+
+        let replayed: Vec<_> = client
+            .replay_block_transactions(BlockId::Number(2), Default::default())
+            .unwrap()
+            .collect();
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[0].gas_used, first.gas_used);
+        assert_eq!(replayed[1].gas_used, second.gas_used);
+        assert_eq!(replayed[1].output, second.output);
+
+        // blocks without their own results replay the single execution result
+        let replayed: Vec<_> = client
+            .replay_block_transactions(BlockId::Number(1), Default::default())
+            .unwrap()
+            .collect();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].gas_used, first.gas_used);
+    }
+
+    #[test]
+    fn should_return_logs_in_chain_order() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        let log = |block_number, transaction_index, log_index| {
+            LocalizedLogEntry {
+                entry: LogEntry {
+                    address: Default::default(),
+                    topics: vec![],
+                    data: vec![],
+                },
+                block_hash: H256::from(block_number),
+                block_number: block_number,
+                transaction_hash: H256::default(),
+                transaction_index: transaction_index,
+                log_index: log_index,
+                transaction_log_index: 0,
+            }
+        };
+        client.set_logs(vec![
+            log(2, 0, 0),
+            log(1, 1, 2),
+            log(1, 0, 1),
+            log(1, 0, 0),
+            log(3, 0, 0),
+        ]);
+
+        let positions = |limit| {
+            let filter = Filter {
+                from_block: BlockId::Earliest,
+                to_block: BlockId::Latest,
+                address: None,
+                topics: vec![],
+                limit: limit,
+            };
+            client
+                .logs(filter)
+                .into_iter()
+                .map(|l| (l.block_number, l.transaction_index, l.log_index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            positions(None),
+            vec![(1, 0, 0), (1, 0, 1), (1, 1, 2), (2, 0, 0), (3, 0, 0)]
+        );
+        // the limit keeps the latest logs
+        assert_eq!(positions(Some(2)), vec![(2, 0, 0), (3, 0, 0)]);
+    }
+
+    #[test]
+    fn should_read_pending_state_overrides() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        let address = Address::from(1);
+        let other = Address::from(2);
+        let position = H128::from(3);
+        client.set_balance(address, U256::from(10));
+        client.set_nonce(address, U256::from(1));
+        client.set_storage(address, position, H128::from(4));
+        client.set_balance(other, U256::from(20));
+
+        client.set_pending_balance(address, U256::from(5));
+        client.set_pending_nonce(address, U256::from(2));
+        client.set_pending_storage(address, position, H128::from(6));
+        client.set_pending_code(address, vec![7]);
+
+        assert_eq!(client.balance(&address, BlockId::Latest), Some(U256::from(10)));
+        assert_eq!(client.balance(&address, BlockId::Pending), Some(U256::from(5)));
+        assert_eq!(client.nonce(&address, BlockId::Latest), Some(U256::from(1)));
+        assert_eq!(client.nonce(&address, BlockId::Pending), Some(U256::from(2)));
+        assert_eq!(
+            client.storage_at(&address, &position, BlockId::Latest),
+            Some(H128::from(4))
+        );
+        assert_eq!(
+            client.storage_at(&address, &position, BlockId::Pending),
+            Some(H128::from(6))
+        );
+        assert_eq!(client.code(&address, BlockId::Latest), Some(None));
+        assert_eq!(client.code(&address, BlockId::Pending), Some(Some(vec![7])));
+        assert!(client.code_hash(&address, BlockId::Latest).is_none());
+        assert!(client.code_hash(&address, BlockId::Pending).is_some());
+
+        // accounts without pending overrides fall back to the latest state
+        assert_eq!(client.balance(&other, BlockId::Pending), Some(U256::from(20)));
+    }
+
+    #[test]
+    fn should_return_chain_id_of_spec() {
+        let spec = Spec::new_test();
+        let chain_id = spec.params().chain_id;
+        let client = TestBlockChainClient::new_with_spec(spec);
+
+        assert_eq!(client.chain_id(), chain_id);
+    }
+
+    #[test]
+    fn should_return_canonical_hash() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(3, EachBlockWith::Nothing);
+
+        assert_eq!(client.canonical_hash(0), Some(client.genesis_hash));
+        for number in 1..4 {
+            assert_eq!(
+                client.canonical_hash(number),
+                client.block_hash(BlockId::Number(number))
+            );
+            assert!(client.canonical_hash(number).is_some());
+        }
+        assert_eq!(client.canonical_hash(4), None);
+    }
+
+    #[test]
+    fn should_return_state_root_of_known_block() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(2, EachBlockWith::Nothing);
+        let header = client.block_header(BlockId::Number(1)).unwrap();
+
+        assert_eq!(client.state_root(BlockId::Number(1)), Some(header.state_root()));
+        assert_eq!(
+            client.state_root(BlockId::Hash(header.hash())),
+            Some(header.state_root())
+        );
+        assert_eq!(client.state_root(BlockId::Number(5)), None);
+        assert_eq!(client.state_root(BlockId::Hash(H256::from(1))), None);
+    }
+
+    #[test]
+    fn should_store_imported_receipts() {
+        let source = TestBlockChainClient::new_with_spec(Spec::new_test());
+        source.add_blocks(1, EachBlockWith::Transaction);
+        let block = source.block(BlockId::Number(1)).unwrap();
+        let transaction = block.view().localized_transaction_at(0).unwrap();
+
+        let receipt = Receipt::new(
+            H256::from(7),
+            U256::from(21_000),
+            U256::zero(),
+            vec![],
+            Bytes::default(),
+            String::default(),
+        );
+        let receipts = ::rlp::encode_list::<Receipt, _>(&[receipt.clone()]).into_vec();
+
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        let hash = client
+            .import_block_with_receipts(block.into_inner(), receipts.clone())
+            .unwrap();
+
+        let localized = client
+            .transaction_receipt(TransactionId::Hash(transaction.hash()))
+            .unwrap();
+        assert_eq!(localized.block_hash, hash);
+        assert_eq!(localized.block_number, 1);
+        assert_eq!(localized.transaction_index, 0);
+        assert_eq!(localized.gas_used, receipt.gas_used);
+        assert_eq!(localized.cumulative_gas_used, receipt.gas_used);
+        assert_eq!(localized.state_root, H256::from(7));
+        assert_eq!(client.block_receipts(&hash), Some(receipts));
+    }
+
+    #[test]
+    fn should_hide_pruned_state() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(10, EachBlockWith::Nothing);
+        let address = Address::from(1);
+        client.set_balance(address, U256::from(5));
+
+        // without history every block has state
+        assert_eq!(client.balance(&address, BlockId::Number(1)), Some(U256::from(5)));
+
+        client.set_history(Some(4));
+        assert_eq!(client.pruning_info().earliest_state, 6);
+        assert_eq!(client.balance(&address, BlockId::Number(3)), None);
+        assert_eq!(client.nonce(&address, BlockId::Number(5)), None);
+        assert_eq!(client.code(&address, BlockId::Number(5)), None);
+        assert_eq!(client.storage_at(&address, &H128::zero(), BlockId::Number(5)), None);
+        assert_eq!(client.balance(&address, BlockId::Number(6)), Some(U256::from(5)));
+        assert_eq!(client.balance(&address, BlockId::Latest), Some(U256::from(5)));
+        // unknown blocks have no state either
+        assert_eq!(client.balance(&address, BlockId::Number(11)), None);
+    }
+
+    #[test]
+    fn should_add_blocks_with_given_params() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        let first = client.chain_info().best_block_number + 1;
+        client.add_blocks_with(3, EachBlockWith::Nothing, |n| {
+            (U256::from(n * 100), 1_000 + n * 10)
+        });
+
+        for n in first..first + 3 {
+            let header = client.block_header(BlockId::Number(n)).unwrap();
+            assert_eq!(header.difficulty(), U256::from(n * 100));
+            assert_eq!(header.timestamp(), 1_000 + n * 10);
+        }
+    }
+
+    #[test]
+    fn should_track_block_total_difficulty() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        let genesis_difficulty = client.block_header(BlockId::Earliest).unwrap().difficulty();
+        assert_eq!(client.block_total_difficulty(BlockId::Earliest), Some(genesis_difficulty));
+
+        client.add_blocks_with(3, EachBlockWith::Nothing, |n| (U256::from(n * 100), 0));
+        let mut expected = genesis_difficulty;
+        for n in 1..4 {
+            expected = expected + U256::from(n * 100);
+            let total = client.block_total_difficulty(BlockId::Number(n)).unwrap();
+            assert_eq!(total, expected);
+            assert!(total > client.block_total_difficulty(BlockId::Number(n - 1)).unwrap());
+        }
+        assert_eq!(client.block_total_difficulty(BlockId::Latest), Some(expected));
+        assert_eq!(client.block_total_difficulty(BlockId::Number(4)), None);
+        assert_eq!(client.block_total_difficulty(BlockId::Hash(H256::from(1))), None);
+    }
+
+    #[test]
+    fn should_add_block_with_author() {
+        let client = TestBlockChainClient::new_with_spec(Spec::new_test());
+        client.add_blocks(1, EachBlockWith::Nothing);
+        let author = Address::from(7);
+        client.add_block_with_author(author, b"sealer".to_vec());
+
+        let header = client.block_header(BlockId::Latest).unwrap();
+        assert_eq!(header.number(), 2);
+        assert_eq!(header.author(), author);
+        assert_eq!(header.extra_data(), b"sealer".to_vec());
+        assert_eq!(header.parent_hash(), client.block_hash(BlockId::Number(1)).unwrap());
+        // bulk blocks keep the client's defaults
+        let first = client.block_header(BlockId::Number(1)).unwrap();
+        assert_eq!(first.author(), Address::default());
+        assert_eq!(first.extra_data(), client.extra_data);
+    }
+}
@@ -79,6 +79,10 @@ pub trait BlockChainClient: Sync + Send {
     /// May not fail on BlockId::Latest.
     fn storage_root(&self, address: &Address, id: BlockId) -> Option<H256>;
 
+    /// Get the state root of the given block.
+    /// Returns `None` if the block is unknown.
+    fn state_root(&self, id: BlockId) -> Option<H256>;
+
     /// Get address nonce at the latest block's state.
     fn latest_nonce(&self, address: &Address) -> U256 {
         self.nonce(address, BlockId::Latest).expect(
@@ -90,6 +94,11 @@ pub trait BlockChainClient: Sync + Send {
     /// Get block hash.
     fn block_hash(&self, id: BlockId) -> Option<H256>;
 
+    /// Get the hash of the canonical block at given height.
+    fn canonical_hash(&self, number: BlockNumber) -> Option<H256> {
+        self.block_hash(BlockId::Number(number))
+    }
+
     /// Get address code at given block's state.
     fn code(&self, address: &Address, id: BlockId) -> Option<Option<Bytes>>;
 
@@ -158,6 +167,14 @@ pub trait BlockChainClient: Sync + Send {
     /// Get transaction receipt with given hash.
     fn transaction_receipt(&self, id: TransactionId) -> Option<LocalizedReceipt>;
 
+    /// Build a receipt for the given transaction out of a (simulated) execution result.
+    /// Returns `None` if the transaction is unknown.
+    fn executed_to_receipt(
+        &self,
+        id: TransactionId,
+        executed: &Executed,
+    ) -> Option<LocalizedReceipt>;
+
     /// Get a tree route between `from` and `to`.
     /// See `BlockChain::tree_route`.
     fn tree_route(&self, from: &H256, to: &H256) -> Option<TreeRoute>;
@@ -289,6 +306,9 @@ pub trait BlockChainClient: Sync + Send {
     /// Get the chain spec name.
     fn spec_name(&self) -> String;
 
+    /// Get the chain id, `None` if the chain spec doesn't define one.
+    fn chain_id(&self) -> Option<u64>;
+
     /// Disable the client from importing blocks. This cannot be undone in this session and indicates
     /// that a subsystem has reason to believe this executable incapable of syncing the chain.
     fn disable(&self);
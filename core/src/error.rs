@@ -172,6 +172,10 @@ pub enum BlockImportError {
     Import(ImportError),
     /// Block error
     Block(BlockError),
+    /// Receipts root of the header doesn't match the supplied receipts
+    ReceiptsRootMismatch(Mismatch<H256>),
+    /// Transactions root of the header doesn't match the block's transactions
+    TransactionsRootMismatch(Mismatch<H256>),
     /// Other error
     Other(String),
 }
@@ -241,6 +245,12 @@ pub enum Error {
     Ethkey(EthkeyError),
     /// Account Provider error.
     AccountProvider(AccountsError),
+    /// Database was initialized with a different genesis block.
+    GenesisMismatch(Mismatch<H256>),
+    /// Database was initialized with a different compression dictionary.
+    DictionaryMismatch,
+    /// Compression dictionary has more entries than can be swapped.
+    DictionaryTooLarge(OutOfBounds<usize>),
 }
 
 impl fmt::Display for Error {
@@ -264,6 +274,15 @@ impl fmt::Display for Error {
             Error::Engine(ref err) => err.fmt(f),
             Error::Ethkey(ref err) => err.fmt(f),
             Error::AccountProvider(ref err) => err.fmt(f),
+            Error::GenesisMismatch(ref mis) => {
+                f.write_fmt(format_args!("Genesis block mismatch: {}", mis))
+            }
+            Error::DictionaryMismatch => {
+                f.write_str("Database was created with another compression dictionary")
+            }
+            Error::DictionaryTooLarge(ref oob) => {
+                f.write_fmt(format_args!("Compression dictionary too large: {}", oob))
+            }
         }
     }
 }
@@ -325,6 +344,12 @@ impl From<BlockImportError> for Error {
         match err {
             BlockImportError::Block(e) => Error::Block(e),
             BlockImportError::Import(e) => Error::Import(e),
+            BlockImportError::ReceiptsRootMismatch(m) => {
+                Error::Block(BlockError::InvalidReceiptsRoot(m))
+            }
+            BlockImportError::TransactionsRootMismatch(m) => {
+                Error::Block(BlockError::InvalidTransactionsRoot(m))
+            }
             BlockImportError::Other(s) => Error::Util(UtilError::from(s)),
         }
     }
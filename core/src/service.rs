@@ -141,9 +141,11 @@ impl ClientService {
     /// check db if correct
     fn correct_db(dbs: Arc<KeyValueDB>) -> Result<(), String> {
         use db::Readable;
-        use blockchain::BlockDetails;
+        use blockchain::{BlockDetails, ExtraKey};
         // get best block hash
-        let best_block_hash = dbs.get(db::COL_EXTRA, b"best").expect("EXTRA db not found");
+        let best_block_hash = dbs
+            .get(db::COL_EXTRA, ExtraKey::Best.as_bytes())
+            .expect("EXTRA db not found");
         match best_block_hash {
             None => {
                 // new db , nothing to do ;
@@ -198,7 +200,7 @@ impl ClientService {
                         .to_vec();
                     let parent_header = ::encoded::Header::new(parent_header_bytes).decode();
                     let parnet_number = parent_header.number();
-                    batch.put(db::COL_EXTRA, b"best", &parent);
+                    batch.put(db::COL_EXTRA, ExtraKey::Best.as_bytes(), &parent);
                     let new_parent_block_detail = BlockDetails {
                         number: parnet_number,
                         total_difficulty: U256::from(*parent_header.difficulty()),
@@ -71,6 +71,8 @@ pub struct CommonParams {
     pub monetary_policy_update: Option<BlockNumber>,
     /// Transaction permission managing contract address.
     pub transaction_permission_contract: Option<Address>,
+    /// Chain id, if the spec defines one.
+    pub chain_id: Option<u64>,
 }
 
 impl From<ajson::spec::Params> for CommonParams {
@@ -83,6 +85,7 @@ impl From<ajson::spec::Params> for CommonParams {
             registrar: p.registrar.map_or_else(Address::new, Into::into),
             monetary_policy_update: p.monetary_policy_update.map(Into::into),
             transaction_permission_contract: p.transaction_permission_contract.map(Into::into),
+            chain_id: p.chain_id.map(Into::into),
         }
     }
 }
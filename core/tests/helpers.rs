@@ -26,7 +26,7 @@ use block::{OpenBlock, Drain};
 use blockchain::{BlockChain, Config as BlockChainConfig};
 use bytes::Bytes;
 use client::{BlockChainClient, ChainNotify, Client, ClientConfig};
-use key::{Ed25519Secret, Ed25519KeyPair};
+use key::{generate_keypair, Ed25519Secret, Ed25519KeyPair};
 use header::Header;
 use transaction::{Action, Transaction, SignedTransaction};
 use views::BlockView;
@@ -96,6 +96,65 @@ pub fn create_test_block_with_data(header: &Header, transactions: &[SignedTransa
     rlp.out()
 }
 
+/// Transaction builder for tests, filling every field not set explicitly with a default.
+pub struct TestTx {
+    tx: Transaction,
+}
+
+impl TestTx {
+    /// Contract creation with the given nonce, no gas price, no value and 100k gas.
+    pub fn new<T: Into<U256>>(nonce: T) -> Self {
+        TestTx {
+            tx: Transaction {
+                nonce: nonce.into(),
+                gas_price: U256::zero(),
+                gas: 100_000.into(),
+                action: Action::Create,
+                value: U256::zero(),
+                data: Vec::new(),
+                transaction_type: ::transaction::DEFAULT_TRANSACTION_TYPE,
+                nonce_bytes: Vec::new(),
+                gas_price_bytes: Vec::new(),
+                gas_bytes: Vec::new(),
+                value_bytes: Vec::new(),
+            },
+        }
+    }
+
+    pub fn gas_price<T: Into<U256>>(mut self, gas_price: T) -> Self {
+        self.tx.gas_price = gas_price.into();
+        self
+    }
+
+    pub fn gas<T: Into<U256>>(mut self, gas: T) -> Self {
+        self.tx.gas = gas.into();
+        self
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.tx.action = action;
+        self
+    }
+
+    pub fn value<T: Into<U256>>(mut self, value: T) -> Self {
+        self.tx.value = value.into();
+        self
+    }
+
+    pub fn data(mut self, data: Bytes) -> Self {
+        self.tx.data = data;
+        self
+    }
+
+    /// Sign with the given key pair, or with a freshly generated one if `None`.
+    pub fn sign(self, keypair: Option<&Ed25519KeyPair>) -> SignedTransaction {
+        match keypair {
+            Some(keypair) => self.tx.sign(keypair.secret(), None),
+            None => self.tx.sign(generate_keypair().secret(), None),
+        }
+    }
+}
+
 pub fn generate_dummy_client(block_number: u32) -> Arc<Client> {
     generate_dummy_client_with_spec_and_data(Spec::new_test, block_number, 0, &[])
 }
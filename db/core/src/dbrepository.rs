@@ -190,6 +190,13 @@ macro_rules! impl_keyvaluedb {
                 }
             }
 
+            fn has_key(&self, db_name: &str, key: &[u8]) -> Result<bool> {
+                match self.dbs.get(db_name) {
+                    Some(ref db) => Ok(db.read().value_size(key).is_some()),
+                    _ => Err(Error::NotFound(db_name.into())),
+                }
+            }
+
             fn keys(&self) -> Option<Vec<String>> {
                 return Some(self.dbs.keys().into_iter().map(|k| k.clone()).collect::<Vec<String>>());
             }
@@ -128,6 +128,16 @@ impl DBTransaction {
 
     /// An iterator visiting all dbs in
     pub fn dbs(&self) -> Keys<&str, Vec<DBOp>> { self.ops.keys() }
+
+    /// Number of operations over all dbs.
+    pub fn len(&self) -> usize {
+        self.dbs()
+            .map(|db_name| self.ops.get_vec(db_name).map_or(0, |ops| ops.len()))
+            .sum()
+    }
+
+    /// Return true if the transaction holds no operations.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
 }
 
 #[cfg(test)]
@@ -151,4 +161,15 @@ mod test {
         assert_eq!(batch.get("test").unwrap(), dbop1);
         assert_eq!(batch.get_vec("test").unwrap(), vec![dbop1, dbop2]);
     }
+
+    #[test]
+    fn should_count_dbtransaction_ops() {
+        let mut batch = DBTransaction::new();
+        assert!(batch.is_empty());
+        batch.put("test", b"1", b"cat");
+        batch.put("test", b"2", b"dog");
+        batch.delete("other", b"3");
+        assert_eq!(batch.len(), 3);
+        assert!(!batch.is_empty());
+    }
 }
@@ -51,6 +51,10 @@ impl MemoryDB {
         }
     }
 
+    pub fn value_size(&self, k: &[u8]) -> Option<usize> {
+        self.data.get(&H256::from(k)).map(|value| value.0.len())
+    }
+
     pub fn delete(&mut self, _k: &[u8]) -> Option<DBValue> { unimplemented!() }
 
     pub fn put(&mut self, _k: &[u8], _v: &DBValue) -> Option<DBValue> { unimplemented!() }
@@ -65,6 +69,9 @@ impl MemoryDB {
 
     pub fn clear(&mut self) { self.data.clear(); }
 
+    /// Reserve capacity for at least `additional` more distinct keys.
+    pub fn reserve(&mut self, additional: usize) { self.data.reserve(additional); }
+
     /// Purge all zero-referenced data from the database.
     pub fn purge(&mut self) { self.data.retain(|_, &mut (_, rc)| rc != 0); }
 
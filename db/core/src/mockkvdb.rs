@@ -21,6 +21,8 @@
 
 use traits::KeyValueDAO;
 use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::ops::Bound;
 use super::{Key, DBValue};
 
 /// Rocksdb mock instance in memory
@@ -36,6 +38,33 @@ impl Mockkvdb {
         }
     }
     pub fn open() -> Self { Mockkvdb::new_default() }
+
+    /// Flip the first byte of the value stored under `k` to simulate disk corruption.
+    /// Only meant for tests. Returns false if there is no (non-empty) value to corrupt.
+    pub fn corrupt(&mut self, k: &[u8]) -> bool {
+        match self.db.get_mut(k) {
+            Some(ref mut v) if !v.is_empty() => {
+                v[0] ^= 0xff;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the value stored under `k` to simulate data loss.
+    /// Only meant for tests. Returns false if the key was not present.
+    pub fn drop_key(&mut self, k: &[u8]) -> bool { self.db.remove(k).is_some() }
+
+    /// Iterate over all entries in ascending key order, so tests relying on the order are
+    /// reproducible. Unlike `iter()`, the map isn't cloned up front: entries are borrowed
+    /// and only copied into boxed slices as they are reached.
+    pub fn iter_sorted<'a>(&'a self) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Box::new(
+            self.db
+                .iter()
+                .map(|(k, v)| (k.to_vec().into_boxed_slice(), v.to_vec().into_boxed_slice())),
+        )
+    }
 }
 
 impl KeyValueDAO for Mockkvdb {
@@ -46,12 +75,30 @@ impl KeyValueDAO for Mockkvdb {
         }
     }
 
+    fn value_size(&self, k: &[u8]) -> Option<usize> { self.db.get(k).map(|v| v.len()) }
+
+    fn get_or_default(&self, k: &[u8], default: DBValue) -> DBValue {
+        self.db.get(k).cloned().unwrap_or(default)
+    }
+
     fn put(&mut self, k: &[u8], v: &DBValue) -> Option<DBValue> {
         let mut ekey = Key::new();
         ekey.append_slice(k);
         self.db.insert(ekey, v.clone())
     }
 
+    fn put_if_absent(&mut self, k: &[u8], v: &DBValue) -> bool {
+        let mut ekey = Key::new();
+        ekey.append_slice(k);
+        match self.db.entry(ekey) {
+            Entry::Vacant(entry) => {
+                entry.insert(v.clone());
+                true
+            }
+            Entry::Occupied(_) => false,
+        }
+    }
+
     fn delete(&mut self, k: &[u8]) -> Option<DBValue> {
         let mut ekey = Key::new();
         ekey.append_slice(k);
@@ -93,6 +140,17 @@ impl KeyValueDAO for Mockkvdb {
                 }),
         )
     }
+
+    fn count_prefix(&self, prefix: &[u8]) -> usize {
+        self.db
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .count()
+    }
+
+    fn len(&self) -> usize { self.db.len() }
+
+    fn is_empty(&self) -> bool { self.db.is_empty() }
 }
 
 #[cfg(test)]
@@ -122,4 +180,134 @@ mod tests {
 
         assert_eq!(db.get(&key1), None);
     }
+    #[test]
+    fn corrupt_and_drop_key() {
+        let mut db = Mockkvdb::new_default();
+
+        let key1: Vec<u8> = vec![1];
+        let key2: Vec<u8> = vec![2];
+        let value: Vec<u8> = vec![0x0f, 0x10];
+
+        db.put(&key1, &DBValue::from_vec(value.clone()));
+        db.put(&key2, &DBValue::from_vec(value.clone()));
+
+        assert!(db.corrupt(&key1));
+        assert_eq!(db.get(&key1).unwrap(), vec![0xf0, 0x10]);
+        assert_eq!(db.get(&key2).unwrap(), value);
+
+        assert!(db.drop_key(&key2));
+        assert_eq!(db.get(&key2), None);
+        assert!(!db.drop_key(&key2));
+        assert!(!db.corrupt(&key2));
+    }
+    #[test]
+    fn len_test() {
+        let mut db = Mockkvdb::new_default();
+        assert!(db.is_empty());
+        assert_eq!(db.len(), 0);
+
+        let key1: Vec<u8> = vec![1];
+        let key2: Vec<u8> = vec![2];
+        let value: Vec<u8> = vec![1];
+
+        db.put(&key1, &DBValue::from_vec(value.clone()));
+        db.put(&key2, &DBValue::from_vec(value.clone()));
+        // overwriting doesn't add an entry
+        db.put(&key1, &DBValue::from_vec(value.clone()));
+        assert_eq!(db.len(), 2);
+        assert!(!db.is_empty());
+
+        db.delete(&key1);
+        assert_eq!(db.len(), 1);
+        db.delete(&key2);
+        assert_eq!(db.len(), 0);
+        assert!(db.is_empty());
+    }
+    #[test]
+    fn multi_get_test() {
+        let mut db = Mockkvdb::new_default();
+
+        let key1: Vec<u8> = vec![1];
+        let key2: Vec<u8> = vec![2];
+        let key3: Vec<u8> = vec![3];
+        let value1: Vec<u8> = vec![1];
+        let value2: Vec<u8> = vec![2];
+
+        db.put(&key1, &DBValue::from_vec(value1.clone()));
+        db.put(&key2, &DBValue::from_vec(value2.clone()));
+
+        let values = db.multi_get(&[&key2, &key3, &key1]);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].as_ref().unwrap(), &value2);
+        assert_eq!(values[1], None);
+        assert_eq!(values[2].as_ref().unwrap(), &value1);
+        assert!(db.multi_get(&[]).is_empty());
+    }
+    #[test]
+    fn value_size_test() {
+        let mut db = Mockkvdb::new_default();
+
+        db.put(&[1], &DBValue::from_vec(vec![1, 2, 3]));
+        db.put(&[2], &DBValue::from_vec(vec![]));
+
+        assert_eq!(db.value_size(&[1]), Some(3));
+        assert_eq!(db.value_size(&[2]), Some(0));
+        assert_eq!(db.value_size(&[3]), None);
+    }
+    #[test]
+    fn count_prefix_test() {
+        let mut db = Mockkvdb::new_default();
+        let value = DBValue::from_vec(vec![1]);
+
+        db.put(&[0, 1], &value);
+        db.put(&[1, 1], &value);
+        db.put(&[1, 2, 3], &value);
+        db.put(&[1, 3], &value);
+        db.put(&[2, 1], &value);
+
+        assert_eq!(db.count_prefix(&[1]), 3);
+        assert_eq!(db.count_prefix(&[1, 2]), 1);
+        assert_eq!(db.count_prefix(&[3]), 0);
+        assert_eq!(db.count_prefix(&[]), 5);
+    }
+    #[test]
+    fn iter_sorted_test() {
+        let mut db = Mockkvdb::new_default();
+        let value = DBValue::from_vec(vec![1]);
+
+        db.put(&[3], &value);
+        db.put(&[1, 2], &value);
+        db.put(&[2], &value);
+        db.put(&[1], &value);
+
+        let keys: Vec<Vec<u8>> = db.iter_sorted().map(|(k, _)| k.to_vec()).collect();
+        assert_eq!(keys, vec![vec![1], vec![1, 2], vec![2], vec![3]]);
+        assert_eq!(db.iter_sorted().count(), db.len());
+        assert!(db.iter_sorted().all(|(_, v)| &*v == &[1][..]));
+    }
+    #[test]
+    fn get_or_default_test() {
+        let mut db = Mockkvdb::new_default();
+        let default = DBValue::from_vec(vec![0]);
+
+        // absent
+        assert_eq!(db.get_or_default(&[1], default.clone()).to_vec(), vec![0]);
+
+        // present
+        db.put(&[1], &DBValue::from_vec(vec![1, 2]));
+        assert_eq!(db.get_or_default(&[1], default).to_vec(), vec![1, 2]);
+    }
+    #[test]
+    fn put_if_absent_test() {
+        let mut db = Mockkvdb::new_default();
+
+        // absent
+        assert!(db.put_if_absent(&[1], &DBValue::from_vec(vec![1])));
+        assert_eq!(db.get(&[1]).unwrap(), vec![1]);
+
+        // present, the stored value is kept
+        assert!(!db.put_if_absent(&[1], &DBValue::from_vec(vec![2])));
+        assert_eq!(db.get(&[1]).unwrap(), vec![1]);
+        assert_eq!(db.len(), 1);
+    }
 }
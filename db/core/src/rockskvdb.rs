@@ -212,6 +212,20 @@ impl KeyValueDAO for Rockskvdb {
         }
     }
 
+    fn value_size(&self, k: &[u8]) -> Option<usize> {
+        match self.overlay.get(k) {
+            Some(KeyState::Insert(ref value)) => Some(value.len()),
+            Some(KeyState::Delete) => None,
+            // rocksdb has no size lookup, the value is read but not copied into a `DBValue`
+            None => {
+                self.db
+                    .get_opt(k, &self.read_options)
+                    .unwrap_or(None)
+                    .map(|r| r.len())
+            }
+        }
+    }
+
     fn put(&mut self, k: &[u8], v: &DBValue) -> Option<DBValue> {
         let mut ekey = Key::new();
         ekey.append_slice(k);
@@ -282,6 +296,10 @@ impl KeyValueDAO for Rockskvdb {
             &self.read_options,
         ))
     }
+
+    /// Estimated number of entries, counted by a full scan. Until the overlay is flushed,
+    /// pending updates may be counted twice and pending deletes are not subtracted.
+    fn len(&self) -> usize { self.iter().count() }
 }
 impl Drop for Rockskvdb {
     fn drop(&mut self) { let _ = self.flush(); }
@@ -25,8 +25,27 @@ use dbtransaction::DBTransaction;
 pub trait KeyValueDAO: Sync + Send {
     /// Get value by key
     fn get(&self, k: &[u8]) -> Option<DBValue>;
+    /// Get values of several keys at once, in the order of `keys`.
+    fn multi_get(&self, keys: &[&[u8]]) -> Vec<Option<DBValue>> {
+        keys.iter().map(|k| self.get(k)).collect()
+    }
+    /// Byte length of the value stored under `k`, without handing out the value. Backends may
+    /// still read the whole value to find it, as RocksDB does.
+    fn value_size(&self, k: &[u8]) -> Option<usize> { self.get(k).map(|v| v.len()) }
+    /// Value stored under `k`, or `default` if there is none.
+    fn get_or_default(&self, k: &[u8], default: DBValue) -> DBValue {
+        self.get(k).unwrap_or(default)
+    }
     /// Insert a key-value pair to db, return value when success, otherwise None
     fn put(&mut self, k: &[u8], v: &DBValue) -> Option<DBValue>;
+    /// Insert a key-value pair unless the key is already present. Return whether it inserted.
+    fn put_if_absent(&mut self, k: &[u8], v: &DBValue) -> bool {
+        if self.value_size(k).is_some() {
+            return false;
+        }
+        self.put(k, v);
+        true
+    }
     /// Delete from db. return the value if the db has the pair.
     fn delete(&mut self, k: &[u8]) -> Option<DBValue>;
     /// Return an iterator
@@ -38,11 +57,24 @@ pub trait KeyValueDAO: Sync + Send {
         &self,
         prefix: &'static [u8],
     ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)>>;
+    /// Count the keys starting with `prefix`.
+    fn count_prefix(&self, prefix: &[u8]) -> usize {
+        self.iter().filter(|(k, _)| k.starts_with(prefix)).count()
+    }
+    /// Number of entries held. Depending on the backend this may be an estimate.
+    fn len(&self) -> usize;
+    /// Return true if no entries are held.
+    fn is_empty(&self) -> bool { self.len() == 0 }
 }
 /// db repository operation.
 pub trait KeyValueDB: Sync + Send {
     /// Get the value by key from the specified db
     fn get(&self, db_name: &str, key: &[u8]) -> Result<Option<DBValue>>;
+    /// Check whether the key is in the specified db, without handing out the value. Depending on
+    /// the backend the value may still be read from disk.
+    fn has_key(&self, db_name: &str, key: &[u8]) -> Result<bool> {
+        self.get(db_name, key).map(|value| value.is_some())
+    }
     fn keys(&self) -> Option<Vec<String>>;
     /// Commit transaction to database and flush to db
     fn write(&self, transaction: DBTransaction) -> Result<()> {
@@ -23,10 +23,13 @@
 //! Disk-backed `HashStore` implementation.
 
 use std::collections::HashMap;
+use std::mem;
 use std::collections::hash_map::Entry;
 use std::sync::Arc;
+use lru_cache::LruCache;
+use parking_lot::Mutex;
 use rlp::*;
-use super::{DB_PREFIX_LEN, LATEST_ERA_KEY};
+use super::{DB_PREFIX_LEN, EARLIEST_ERA_KEY, LATEST_ERA_KEY};
 use traits::JournalDB;
 use kvdb::{KeyValueDB, DBTransaction, HashStore, DBValue, MemoryDB};
 use aion_types::H256;
@@ -44,27 +47,233 @@ pub struct ArchiveDB {
     overlay: MemoryDB,
     backing: Arc<KeyValueDB>,
     latest_era: Option<u64>,
+    // lowest era journaled since the record was introduced, `None` for older databases
+    earliest_era: Option<u64>,
     db_name: &'static str,
+    // values recently read from the backing store, `None` when disabled
+    read_cache: Option<Mutex<LruCache<H256, DBValue>>>,
 }
 
 impl ArchiveDB {
     /// Create a new instance from a key-value db.
     pub fn new(backing: Arc<KeyValueDB>, db_name: &'static str) -> ArchiveDB {
-        let latest_era = backing
-            .get(db_name, &LATEST_ERA_KEY)
-            .expect("Low-level database error.")
-            .map(|val| decode::<u64>(&val));
-        ArchiveDB {
+        ArchiveDB::open(backing, db_name).expect("Low-level database error.")
+    }
+
+    /// Create a new instance from a key-value db, failing with `ErrorKind::Db` if the backing
+    /// store can't be read and with `ErrorKind::Decoder` if an era record is malformed.
+    pub fn open(
+        backing: Arc<KeyValueDB>,
+        db_name: &'static str,
+    ) -> Result<ArchiveDB, UtilError>
+    {
+        let latest_era = match backing.get(db_name, &LATEST_ERA_KEY)? {
+            Some(val) => Some(UntrustedRlp::new(&val).as_val::<u64>()?),
+            None => None,
+        };
+        let earliest_era = match backing.get(db_name, &EARLIEST_ERA_KEY)? {
+            Some(val) => Some(UntrustedRlp::new(&val).as_val::<u64>()?),
+            None => None,
+        };
+        Ok(ArchiveDB {
             overlay: MemoryDB::new(),
             backing: backing,
             latest_era: latest_era,
+            earliest_era: earliest_era,
             db_name: db_name,
+            read_cache: None,
+        })
+    }
+
+    /// Keep up to `size` values read from the backing store in memory, so that hot nodes
+    /// aren't fetched again on every read. A size of 0 disables the cache, which is the default.
+    pub fn set_read_cache_size(&mut self, size: usize) {
+        self.read_cache = match size {
+            0 => None,
+            size => Some(Mutex::new(LruCache::new(size))),
+        };
+    }
+
+    /// Get a value from the overlay or the backing store, failing with `ErrorKind::Db`
+    /// if the backing store can't be read.
+    pub fn try_get(&self, key: &H256) -> Result<Option<DBValue>, UtilError> {
+        if let Some((d, rc)) = self.overlay.raw(key) {
+            if rc > 0 {
+                return Ok(Some(d));
+            }
+        }
+        let cache = match self.read_cache {
+            Some(ref cache) => cache,
+            None => return self.try_payload(key),
+        };
+        if let Some(value) = cache.lock().get_mut(key) {
+            return Ok(Some(value.clone()));
+        }
+        let value = self.try_payload(key)?;
+        if let Some(ref value) = value {
+            cache.lock().insert(*key, value.clone());
         }
+        Ok(value)
     }
 
-    fn payload(&self, key: &H256) -> Option<DBValue> {
+    /// Apply `f` to every key-value pair in the backing column, followed by every inserted
+    /// entry of the overlay which has not been flushed to the backing database yet.
+    pub fn for_each<F: FnMut(&[u8], &[u8])>(&self, mut f: F) {
+        for (key, value) in self.backing.iter(self.db_name) {
+            f(&key, &value);
+        }
+
+        for (key, _) in self.overlay.keys() {
+            if let Some((value, rc)) = self.overlay.raw(&key) {
+                if rc > 0 && self.payload(&key).is_none() {
+                    f(&key, &value);
+                }
+            }
+        }
+    }
+
+    /// Write the inserts of the overlay to `batch` and clear it, without recording an era.
+    /// Returns the number of operations drained from the overlay.
+    ///
+    /// This is meant for maintenance, e.g. persisting the overlay before a clean shutdown.
+    /// Normal operation should commit through `journal_under`, which also keeps
+    /// the latest era up to date.
+    pub fn flush(&mut self, batch: &mut DBTransaction) -> u32 {
+        let mut inserts = 0usize;
+        let mut deletes = 0usize;
+
+        for i in self.overlay.drain() {
+            let (key, (value, rc)) = i;
+            if rc > 0 {
+                batch.put(self.db_name, &key, &value);
+                inserts += 1;
+            }
+            if rc < 0 {
+                assert!(rc == -1);
+                deletes += 1;
+            }
+        }
+        (inserts + deletes) as u32
+    }
+
+    /// Copy every entry of the backing database, plus the inserts of the overlay which are not
+    /// flushed yet, into the `dest_name` column of `dest`, writing at most `batch_size` entries
+    /// per transaction. Keys are preserved exactly. Returns the number of entries copied.
+    ///
+    /// This is meant for migrating an archive to another `KeyValueDB` backend.
+    pub fn copy_to(
+        &self,
+        dest: Arc<KeyValueDB>,
+        dest_name: &'static str,
+        batch_size: usize,
+    ) -> Result<usize, UtilError>
+    {
+        let batch_size = ::std::cmp::max(batch_size, 1);
+        let mut batch = DBTransaction::with_capacity(batch_size);
+        let mut pending = 0usize;
+        let mut copied = 0usize;
+
+        // like `for_each`, skip overlay inserts of keys which are already in the backing column
+        let overlay = self.overlay.keys().into_iter().filter_map(|(key, _)| {
+            self.overlay
+                .raw(&key)
+                .and_then(|(value, rc)| {
+                    if rc > 0 && self.payload(&key).is_none() {
+                        Some(value)
+                    } else {
+                        None
+                    }
+                })
+                .map(|value| (key.to_vec().into_boxed_slice(), value.to_vec().into_boxed_slice()))
+        });
+        for (key, value) in self.backing.iter(self.db_name).chain(overlay) {
+            batch.put(dest_name, &key, &value);
+            pending += 1;
+            copied += 1;
+            if pending == batch_size {
+                dest.write(mem::replace(
+                    &mut batch,
+                    DBTransaction::with_capacity(batch_size),
+                ))?;
+                pending = 0;
+            }
+        }
+        if pending > 0 {
+            dest.write(batch)?;
+        }
+        Ok(copied)
+    }
+
+    /// Insert all `values` into the overlay, returning their hashes in order.
+    /// The overlay grows once for the whole batch instead of once per value.
+    pub fn insert_batch(&mut self, values: &[&[u8]]) -> Vec<H256> {
+        self.overlay.reserve(values.len());
+        values
+            .iter()
+            .map(|value| self.overlay.insert(value))
+            .collect()
+    }
+
+    /// Discard the uncommitted inserts and removes of the overlay, leaving the backing
+    /// database untouched. Returns the number of entries discarded.
+    ///
+    /// This lets callers abort a block import without leaving speculative changes behind.
+    pub fn rollback_overlay(&mut self) -> usize { self.overlay.drain().len() }
+
+    /// Number of keys with an uncommitted insert or remove in the overlay. Unlike the count
+    /// returned by `rollback_overlay`, keys whose inserts and removes cancelled out are not
+    /// included.
+    pub fn overlay_len(&self) -> usize { self.overlay.keys().len() }
+
+    /// Whether the key has an uncommitted insert in the overlay, regardless of the backing
+    /// database.
+    pub fn overlay_contains(&self, key: &H256) -> bool {
+        self.overlay.raw(key).map_or(false, |(_, rc)| rc > 0)
+    }
+
+    /// Lowest and highest eras journaled so far, or `None` if nothing has been committed.
+    ///
+    /// The lowest era is only recorded since this query was introduced, so databases written
+    /// by older versions report the first era committed after the upgrade.
+    pub fn era_range(&self) -> Option<(u64, u64)> {
+        match (self.earliest_era, self.latest_era) {
+            (Some(earliest), Some(latest)) => Some((earliest, latest)),
+            _ => None,
+        }
+    }
+
+    /// Reference count of a single key, as it would be reported by `keys()`: the overlay's
+    /// count plus one if the key is already in the backing database.
+    pub fn ref_count(&self, key: &H256) -> i32 {
+        let overlay_refs = self.overlay.raw(key).map_or(0, |(_, rc)| rc);
+        let backing_refs = if self.payload(key).is_some() { 1 } else { 0 };
+        overlay_refs + backing_refs
+    }
+
+    /// Whether the key is present, with the same answer as `contains` but without copying
+    /// the value out of the overlay or the backing store. Note that the RocksDB backend
+    /// still reads the value from disk to answer.
+    pub fn contains_key(&self, key: &H256) -> bool {
+        if self.overlay.contains(key) {
+            return true;
+        }
         self.backing
-            .get(self.db_name, key)
+            .has_key(self.db_name, key)
+            .expect("Low-level database error. Some issue with your hard disk?")
+    }
+
+    fn try_payload(&self, key: &H256) -> Result<Option<DBValue>, UtilError> {
+        Ok(self.backing.get(self.db_name, key)?)
+    }
+
+    fn uncache(&self, key: &H256) {
+        if let Some(ref cache) = self.read_cache {
+            cache.lock().remove(key);
+        }
+    }
+
+    fn payload(&self, key: &H256) -> Option<DBValue> {
+        self.try_payload(key)
             .expect("Low-level database error. Some issue with your hard disk?")
     }
 }
@@ -91,21 +300,23 @@ impl HashStore for ArchiveDB {
     }
 
     fn get(&self, key: &H256) -> Option<DBValue> {
-        if let Some((d, rc)) = self.overlay.raw(key) {
-            if rc > 0 {
-                return Some(d);
-            }
-        }
-        self.payload(key)
+        self.try_get(key)
+            .expect("Low-level database error. Some issue with your hard disk?")
     }
 
     fn contains(&self, key: &H256) -> bool { self.get(key).is_some() }
 
     fn insert(&mut self, value: &[u8]) -> H256 { self.overlay.insert(value) }
 
-    fn emplace(&mut self, key: H256, value: DBValue) { self.overlay.emplace(key, value); }
+    fn emplace(&mut self, key: H256, value: DBValue) {
+        self.uncache(&key);
+        self.overlay.emplace(key, value);
+    }
 
-    fn remove(&mut self, key: &H256) { self.overlay.remove(key); }
+    fn remove(&mut self, key: &H256) {
+        self.uncache(key);
+        self.overlay.remove(key);
+    }
 }
 
 impl JournalDB for ArchiveDB {
@@ -114,7 +325,12 @@ impl JournalDB for ArchiveDB {
             overlay: self.overlay.clone(),
             backing: self.backing.clone(),
             latest_era: self.latest_era,
+            earliest_era: self.earliest_era,
             db_name: self.db_name.clone(),
+            read_cache: self
+                .read_cache
+                .as_ref()
+                .map(|cache| Mutex::new(LruCache::new(cache.lock().capacity()))),
         })
     }
 
@@ -129,26 +345,17 @@ impl JournalDB for ArchiveDB {
         _id: &H256,
     ) -> Result<u32, UtilError>
     {
-        let mut inserts = 0usize;
-        let mut deletes = 0usize;
-
-        for i in self.overlay.drain() {
-            let (key, (value, rc)) = i;
-            if rc > 0 {
-                batch.put(self.db_name, &key, &value);
-                inserts += 1;
-            }
-            if rc < 0 {
-                assert!(rc == -1);
-                deletes += 1;
-            }
-        }
+        let ops = self.flush(batch);
 
         if self.latest_era.map_or(true, |e| now > e) {
             batch.put(self.db_name, &LATEST_ERA_KEY, &encode(&now));
             self.latest_era = Some(now);
         }
-        Ok((inserts + deletes) as u32)
+        if self.earliest_era.map_or(true, |e| now < e) {
+            batch.put(self.db_name, &EARLIEST_ERA_KEY, &encode(&now));
+            self.earliest_era = Some(now);
+        }
+        Ok(ops)
     }
 
     fn mark_canonical(
@@ -169,12 +376,7 @@ impl JournalDB for ArchiveDB {
         for i in self.overlay.drain() {
             let (key, (value, rc)) = i;
             if rc > 0 {
-                if self
-                    .backing
-                    .get(self.db_name, &key)
-                    .expect("state db not found")
-                    .is_some()
-                {
+                if self.try_payload(&key)?.is_some() {
                     return Err(BaseDataError::AlreadyExists(key).into());
                 }
                 batch.put(self.db_name, &key, &value);
@@ -182,14 +384,10 @@ impl JournalDB for ArchiveDB {
             }
             if rc < 0 {
                 assert!(rc == -1);
-                if self
-                    .backing
-                    .get(self.db_name, &key)
-                    .expect("state db not found")
-                    .is_none()
-                {
+                if self.try_payload(&key)?.is_none() {
                     return Err(BaseDataError::NegativelyReferencedHash(key).into());
                 }
+                self.uncache(&key);
                 batch.delete(self.db_name, &key);
                 deletes += 1;
             }
@@ -220,7 +418,346 @@ mod tests {
     use kvdb::{HashStore, DBValue};
     use super::*;
     use JournalDB;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use kvdb::{MockDbRepository};
+    use error::ErrorKind;
+
+    // Backing store which counts reads of the wrapped repository.
+    struct CountingDb {
+        inner: MockDbRepository,
+        gets: AtomicUsize,
+    }
+
+    impl KeyValueDB for CountingDb {
+        fn get(&self, db_name: &str, key: &[u8]) -> ::kvdb::Result<Option<DBValue>> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(db_name, key)
+        }
+
+        fn keys(&self) -> Option<Vec<String>> { self.inner.keys() }
+
+        fn write_buffered(&self, transaction: DBTransaction) {
+            self.inner.write_buffered(transaction)
+        }
+
+        fn iter(&self, db_name: &'static str) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)>> {
+            self.inner.iter(db_name)
+        }
+
+        fn get_by_prefix(&self, db_name: &'static str, prefix: &[u8]) -> Option<Box<[u8]>> {
+            self.inner.get_by_prefix(db_name, prefix)
+        }
+
+        fn iter_from_prefix<'a>(
+            &'a self,
+            db_name: &'static str,
+            prefix: &'static [u8],
+        ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>
+        {
+            self.inner.iter_from_prefix(db_name, prefix)
+        }
+    }
+    #[test]
+    fn open_reports_backing_errors() {
+        // the column doesn't exist, so every read of the backing store fails
+        let result = ArchiveDB::open(Arc::new(MockDbRepository::init(vec![])), "test");
+        match result.map(|_| ()).unwrap_err().kind() {
+            &ErrorKind::Db(_) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn open_reports_malformed_latest_era() {
+        let backing = Arc::new(MockDbRepository::init(vec!["test".into()]));
+        let mut batch = DBTransaction::new();
+        batch.put("test", &LATEST_ERA_KEY, &[0xc1, 0x80]);
+        backing.write(batch).unwrap();
+
+        let result = ArchiveDB::open(backing, "test");
+        match result.map(|_| ()).unwrap_err().kind() {
+            &ErrorKind::Decoder(_) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn try_get_reads_overlay_and_backing() {
+        let mut jdb = ArchiveDB::open(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        )
+        .unwrap();
+        let foo = jdb.insert(b"foo");
+        assert_eq!(jdb.try_get(&foo).unwrap().unwrap().to_vec(), b"foo".to_vec());
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+        assert_eq!(jdb.try_get(&foo).unwrap().unwrap().to_vec(), b"foo".to_vec());
+        assert!(jdb.try_get(&blake2b(b"bar")).unwrap().is_none());
+    }
+
+    #[test]
+    fn for_each_visits_backing_and_overlay() {
+        let mut jdb = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        let foo = jdb.insert(b"foo");
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+        let bar = jdb.insert(b"bar");
+        // already flushed, must not be visited twice
+        jdb.insert(b"foo");
+
+        let mut visited = HashMap::new();
+        jdb.for_each(|k, v| {
+            *visited.entry(k.to_vec()).or_insert(0) += 1;
+            if k == &*foo {
+                assert_eq!(v, b"foo");
+            }
+            if k == &*bar {
+                assert_eq!(v, b"bar");
+            }
+        });
+
+        assert_eq!(visited.get(&foo.to_vec()), Some(&1));
+        assert_eq!(visited.get(&bar.to_vec()), Some(&1));
+        // the era records live in the backing column as well
+        assert!(visited.contains_key(&LATEST_ERA_KEY.to_vec()));
+        assert!(visited.contains_key(&EARLIEST_ERA_KEY.to_vec()));
+        assert_eq!(visited.len(), 4);
+    }
+
+    #[test]
+    fn ref_count_combines_overlay_and_backing() {
+        let mut jdb = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        assert_eq!(jdb.ref_count(&blake2b(b"foo")), 0);
+
+        // overlay only
+        let foo = jdb.insert(b"foo");
+        assert_eq!(jdb.ref_count(&foo), 1);
+        jdb.insert(b"foo");
+        assert_eq!(jdb.ref_count(&foo), 2);
+
+        // backing only
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+        assert_eq!(jdb.ref_count(&foo), 1);
+
+        // both
+        jdb.insert(b"foo");
+        assert_eq!(jdb.ref_count(&foo), 2);
+        jdb.remove(&foo);
+        jdb.remove(&foo);
+        assert_eq!(jdb.ref_count(&foo), 0);
+        assert_eq!(jdb.keys().get(&foo).cloned().unwrap_or(0), jdb.ref_count(&foo));
+    }
+
+    #[test]
+    fn contains_key_agrees_with_contains() {
+        let mut jdb = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        let missing = blake2b(b"missing");
+        assert!(!jdb.contains_key(&missing));
+
+        // overlay only
+        let foo = jdb.insert(b"foo");
+        assert!(jdb.contains_key(&foo));
+
+        // disk only
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+        assert!(jdb.contains_key(&foo));
+
+        // removed from the overlay before reaching the disk
+        let bar = jdb.insert(b"bar");
+        jdb.remove(&bar);
+        assert!(!jdb.contains_key(&bar));
+
+        // removed after reaching the disk, which an archive keeps
+        jdb.remove(&foo);
+        assert!(jdb.contains_key(&foo));
+
+        for key in &[missing, foo, bar] {
+            assert_eq!(jdb.contains_key(key), jdb.contains(key));
+        }
+    }
+
+    #[test]
+    fn rollback_overlay_discards_uncommitted_changes() {
+        let mut jdb = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        let foo = jdb.insert(b"foo");
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+
+        let bar = jdb.insert(b"bar");
+        let baz = jdb.insert(b"baz");
+        jdb.remove(&foo);
+        assert_eq!(jdb.rollback_overlay(), 3);
+        assert_eq!(jdb.rollback_overlay(), 0);
+
+        assert!(!jdb.contains(&bar));
+        assert!(!jdb.contains(&baz));
+        assert_eq!(jdb.get(&foo).unwrap().to_vec(), b"foo".to_vec());
+        assert_eq!(jdb.keys().get(&foo), Some(&1));
+
+        // nothing of the abandoned changes reaches the disk on the next commit
+        jdb.commit_batch(1, &blake2b(b"1"), None).unwrap();
+        assert!(!jdb.contains(&bar));
+        assert!(!jdb.contains(&baz));
+        assert!(jdb.contains(&foo));
+    }
+
+    #[test]
+    fn overlay_tracks_uncommitted_changes() {
+        let backing = Arc::new(MockDbRepository::init(vec!["test".into()]));
+        let mut jdb = ArchiveDB::new(backing.clone(), "test");
+        assert_eq!(jdb.overlay_len(), 0);
+
+        let foo = jdb.insert(b"foo");
+        assert!(jdb.overlay_contains(&foo));
+        assert!(backing.get("test", &foo).unwrap().is_none());
+        assert_eq!(jdb.overlay_len(), 1);
+
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+        assert!(!jdb.overlay_contains(&foo));
+        assert!(backing.get("test", &foo).unwrap().is_some());
+        assert_eq!(jdb.overlay_len(), 0);
+
+        // a pending remove is counted but not contained
+        jdb.remove(&foo);
+        assert!(!jdb.overlay_contains(&foo));
+        assert_eq!(jdb.overlay_len(), 1);
+        assert!(jdb.contains(&foo));
+
+        // an insert cancelling the remove leaves nothing pending, yet an entry to roll back
+        jdb.insert(b"foo");
+        assert_eq!(jdb.overlay_len(), 0);
+        assert_eq!(jdb.rollback_overlay(), 1);
+    }
+
+    #[test]
+    fn copy_to_preserves_keys() {
+        let mut jdb = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        for i in 0..5u8 {
+            jdb.insert(&[i]);
+            jdb.commit_batch(i as u64, &blake2b(&[i]), None).unwrap();
+        }
+        // flushed along with the backing entries
+        let pending = jdb.insert(b"pending");
+        // inserted again after being committed, copied only once
+        jdb.insert(&[0]);
+
+        let dest = Arc::new(MockDbRepository::init(vec!["dest".into()]));
+        // the five values, the two era records and the pending value
+        assert_eq!(jdb.copy_to(dest.clone(), "dest", 2).unwrap(), 8);
+
+        let copy = ArchiveDB::new(dest.clone(), "dest");
+        assert_eq!(copy.keys(), jdb.keys());
+        assert_eq!(copy.latest_era(), Some(4));
+        assert_eq!(copy.era_range(), Some((0, 4)));
+        assert_eq!(copy.get(&pending).unwrap().to_vec(), b"pending".to_vec());
+        let raw: Vec<_> = jdb.backing().iter("test").collect();
+        for (key, value) in raw {
+            assert_eq!(dest.get("dest", &key).unwrap().unwrap().to_vec(), value.to_vec());
+        }
+    }
+
+    #[test]
+    fn insert_batch_matches_insert() {
+        let mut batched = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        let mut single = ArchiveDB::new(
+            Arc::new(MockDbRepository::init(vec!["test".into()])),
+            "test",
+        );
+        let values: Vec<&[u8]> = vec![b"foo", b"bar", b"foo", b"baz"];
+
+        let hashes = batched.insert_batch(&values);
+        let expected: Vec<H256> = values.iter().map(|value| single.insert(value)).collect();
+        assert_eq!(hashes, expected);
+        assert_eq!(hashes[0], hashes[2]);
+        assert_eq!(batched.keys(), single.keys());
+        assert_eq!(batched.ref_count(&hashes[0]), 2);
+        assert!(batched.insert_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn read_cache_spares_backing_reads() {
+        let backing = Arc::new(CountingDb {
+            inner: MockDbRepository::init(vec!["test".into()]),
+            gets: AtomicUsize::new(0),
+        });
+        let mut jdb = ArchiveDB::new(backing.clone(), "test");
+        jdb.set_read_cache_size(16);
+        let foo = jdb.insert(b"foo");
+        jdb.commit_batch(0, &blake2b(b"0"), None).unwrap();
+
+        let reads = backing.gets.load(Ordering::SeqCst);
+        assert_eq!(jdb.get(&foo).unwrap().to_vec(), b"foo".to_vec());
+        assert_eq!(backing.gets.load(Ordering::SeqCst), reads + 1);
+        assert_eq!(jdb.get(&foo).unwrap().to_vec(), b"foo".to_vec());
+        assert_eq!(backing.gets.load(Ordering::SeqCst), reads + 1);
+
+        // touching the key drops it from the cache
+        jdb.emplace(foo, DBValue::from_slice(b"foo"));
+        jdb.remove(&foo);
+        jdb.remove(&foo);
+        assert_eq!(jdb.get(&foo).unwrap().to_vec(), b"foo".to_vec());
+        assert_eq!(backing.gets.load(Ordering::SeqCst), reads + 2);
+
+        // without the cache every read goes to the backing store
+        jdb.set_read_cache_size(0);
+        jdb.get(&foo);
+        jdb.get(&foo);
+        assert_eq!(backing.gets.load(Ordering::SeqCst), reads + 4);
+    }
+
+    #[test]
+    fn era_range_tracks_committed_eras() {
+        let backing = Arc::new(MockDbRepository::init(vec!["test".into()]));
+        let mut jdb = ArchiveDB::new(backing.clone(), "test");
+        assert_eq!(jdb.era_range(), None);
+
+        for era in &[5u64, 3, 7, 4] {
+            jdb.insert(&[*era as u8]);
+            jdb.commit_batch(*era, &blake2b(&[*era as u8]), None).unwrap();
+        }
+        assert_eq!(jdb.era_range(), Some((3, 7)));
+
+        // both bounds are persisted
+        let jdb = ArchiveDB::new(backing, "test");
+        assert_eq!(jdb.era_range(), Some((3, 7)));
+    }
+
+    #[test]
+    fn flush_keeps_latest_era() {
+        let backing = Arc::new(MockDbRepository::init(vec!["test".into()]));
+        let mut jdb = ArchiveDB::new(backing.clone(), "test");
+        jdb.insert(b"foo");
+        jdb.commit_batch(3, &blake2b(b"3"), None).unwrap();
+        assert_eq!(jdb.latest_era(), Some(3));
+
+        let bar = jdb.insert(b"bar");
+        let mut batch = DBTransaction::new();
+        assert_eq!(jdb.flush(&mut batch), 1);
+        backing.write(batch).unwrap();
+        assert_eq!(jdb.latest_era(), Some(3));
+
+        // the data is on disk and the stored era is untouched on reopen
+        let jdb = ArchiveDB::new(backing, "test");
+        assert_eq!(jdb.latest_era(), Some(3));
+        assert_eq!(jdb.get(&bar).unwrap().to_vec(), b"bar".to_vec());
+    }
+
     #[test]
     fn insert_same_in_fork() {
         // history is 1
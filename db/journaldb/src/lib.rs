@@ -27,6 +27,7 @@ extern crate heapsize;
 extern crate log;
 
 extern crate aion_types;
+extern crate lru_cache;
 extern crate acore_bytes as bytes;
 extern crate parking_lot;
 extern crate plain_hasher;
@@ -124,6 +125,8 @@ pub fn new(
 // all keys must be at least 12 bytes
 const DB_PREFIX_LEN: usize = ::kvdb::PREFIX_LEN;
 const LATEST_ERA_KEY: [u8; ::kvdb::PREFIX_LEN] = [b'l', b'a', b's', b't', 0, 0, 0, 0, 0, 0, 0, 0];
+const EARLIEST_ERA_KEY: [u8; ::kvdb::PREFIX_LEN] =
+    [b'e', b'a', b'r', b'l', b'i', b'e', b's', b't', 0, 0, 0, 0];
 
 #[cfg(test)]
 mod tests {
@@ -26,7 +26,7 @@ use std::str::FromStr;
 use std::fmt;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de::{Error, Visitor};
-use rustc_hex::ToHex;
+use rustc_hex::{FromHex, ToHex};
 use aion_types::{H64 as Hash64, H256 as Hash256, H520 as Hash520};
 use ethbloom::Bloom as Hash2048;
 
@@ -56,28 +56,44 @@ macro_rules! impl_hash {
                         write!(formatter, "a 0x-prefixed hex-encoded hash")
                     }
 
+                    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+                    where E: Error {
+                        Ok($name($inner::from(value)))
+                    }
+
+                    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+                    where E: Error {
+                        if value < 0 {
+                            return Err(Error::custom(
+                                format!("Invalid hash value {}: negative", value).as_str(),
+                            ));
+                        }
+                        self.visit_u64(value as u64)
+                    }
+
                     fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
                     where E: Error {
-                        let value = match value.len() {
-                            0 => $inner::from(0),
-                            2 if value == "0x" => $inner::from(0),
-                            _ if value.starts_with("0x") => {
-                                $inner::from_str(&value[2..]).map_err(|e| {
-                                    Error::custom(
-                                        format!("Invalid hex value {}: {}", value, e).as_str(),
-                                    )
-                                })?
-                            }
-                            _ => {
-                                $inner::from_str(value).map_err(|e| {
-                                    Error::custom(
-                                        format!("Invalid hex value {}: {}", value, e).as_str(),
-                                    )
-                                })?
-                            }
+                        let hex = if value.starts_with("0x") {
+                            &value[2..]
+                        } else {
+                            value
                         };
+                        // short values are right-aligned, like integers
+                        let width = $inner::len() * 2;
+                        let hex = format!("{:0>width$}", hex, width = width);
+                        let hash = $inner::from_str(&hex).map_err(|e| {
+                            Error::custom(
+                                format!(
+                                    "Invalid hex value {}: expected {} bytes: {}",
+                                    value,
+                                    $inner::len(),
+                                    e
+                                )
+                                .as_str(),
+                            )
+                        })?;
 
-                        Ok($name(value))
+                        Ok($name(hash))
                     }
 
                     fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
@@ -107,12 +123,76 @@ impl_hash!(H256, Hash256);
 impl_hash!(H520, Hash520);
 impl_hash!(Bloom, Hash2048);
 
+/// Lenient json deserialization of hex-encoded byte sequences of any length.
+///
+/// Unlike `bytes::Bytes`, odd-length values are rejected instead of being padded.
+#[derive(Default, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+pub struct HexBytes(pub Vec<u8>);
+
+impl From<HexBytes> for Vec<u8> {
+    fn from(other: HexBytes) -> Vec<u8> { other.0 }
+}
+
+impl From<Vec<u8>> for HexBytes {
+    fn from(i: Vec<u8>) -> Self { HexBytes(i) }
+}
+
+impl<'a> Deserialize<'a> for HexBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'a> {
+        struct HexBytesVisitor;
+
+        impl<'b> Visitor<'b> for HexBytesVisitor {
+            type Value = HexBytes;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a 0x-prefixed hex-encoded byte sequence")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where E: Error {
+                let hex = if value.starts_with("0x") {
+                    &value[2..]
+                } else {
+                    value
+                };
+                if hex.len() % 2 == 1 {
+                    return Err(Error::custom(
+                        format!("Invalid hex value {}: odd number of digits", value).as_str(),
+                    ));
+                }
+                let bytes = hex.from_hex().map_err(|e| {
+                    Error::custom(format!("Invalid hex value {}: {}", value, e).as_str())
+                })?;
+
+                Ok(HexBytes(bytes))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+            where E: Error {
+                self.visit_str(value.as_ref())
+            }
+        }
+
+        deserializer.deserialize_any(HexBytesVisitor)
+    }
+}
+
+impl Serialize for HexBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        let mut hex = "0x".to_owned();
+        hex.push_str(&self.0.to_hex());
+        serializer.serialize_str(&hex)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
     use serde_json;
     use aion_types;
-    use hash::H256;
+    use hash::{H256, HexBytes};
 
     #[test]
     fn hash_deserialization() {
@@ -132,6 +212,33 @@ mod test {
         );
     }
 
+    #[test]
+    fn hash_deserialization_from_integers() {
+        let s = r#"[0, "0x01", 258]"#;
+        let deserialized: Vec<H256> = serde_json::from_str(s).unwrap();
+        assert_eq!(
+            deserialized,
+            vec![
+                H256(aion_types::H256::from(0)),
+                H256(aion_types::H256::from(1)),
+                H256(aion_types::H256::from(0x0102)),
+            ]
+        );
+        assert!(serde_json::from_str::<H256>("-1").is_err());
+    }
+
+    #[test]
+    fn hash_deserialization_error_names_value_and_width() {
+        let s = r#"["0x01", "0x0g", "0x02"]"#;
+        let message = serde_json::from_str::<Vec<H256>>(s).unwrap_err().to_string();
+        assert!(message.contains("Invalid hex value 0x0g: expected 32 bytes"));
+
+        let too_long = format!(r#""0x{}""#, "11".repeat(33));
+        let message = serde_json::from_str::<H256>(&too_long).unwrap_err().to_string();
+        assert!(message.contains(&too_long[1..too_long.len() - 1]));
+        assert!(message.contains("expected 32 bytes"));
+    }
+
     #[test]
     fn hash_into() {
         assert_eq!(
@@ -139,4 +246,34 @@ mod test {
             H256(aion_types::H256::from(0)).into()
         );
     }
+
+    #[test]
+    fn hex_bytes_deserialization() {
+        let s = r#"["", "0x", "0x12", "0x0aBcDe"]"#;
+        let deserialized: Vec<HexBytes> = serde_json::from_str(s).unwrap();
+        assert_eq!(
+            deserialized,
+            vec![
+                HexBytes(vec![]),
+                HexBytes(vec![]),
+                HexBytes(vec![0x12]),
+                HexBytes(vec![0x0a, 0xbc, 0xde]),
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_bytes_odd_length_fails() {
+        assert!(serde_json::from_str::<HexBytes>(r#""0x001""#).is_err());
+        assert!(serde_json::from_str::<HexBytes>(r#""1""#).is_err());
+    }
+
+    #[test]
+    fn hex_bytes_serialization() {
+        let bytes = HexBytes(vec![0x0a, 0xbc, 0xde]);
+        assert_eq!(serde_json::to_string(&bytes).unwrap(), r#""0x0abcde""#);
+        assert_eq!(serde_json::to_string(&HexBytes::default()).unwrap(), r#""0x""#);
+        let v: Vec<u8> = bytes.into();
+        assert_eq!(v, vec![0x0a, 0xbc, 0xde]);
+    }
 }
@@ -46,6 +46,9 @@ pub struct Params {
     /// Transaction permission contract address.
     #[serde(rename = "transactionPermissionContract")]
     pub transaction_permission_contract: Option<Address>,
+    /// Chain id, used for transaction signing.
+    #[serde(rename = "chainID")]
+    pub chain_id: Option<Uint>,
 }
 
 #[cfg(test)]
@@ -67,6 +70,20 @@ mod tests {
         assert_eq!(deserialized.maximum_extra_data_size, Uint(U256::from(0x20)));
         assert_eq!(deserialized.min_gas_limit, Uint(U256::from(0x1388)));
         assert_eq!(deserialized.gas_limit_bound_divisor, Uint(U256::from(0x20)));
+        assert_eq!(deserialized.chain_id, None);
+    }
+
+    #[test]
+    fn params_deserialization_with_chain_id() {
+        let s = r#"{
+            "maximumExtraDataSize": "0x20",
+            "minGasLimit": "0x1388",
+            "gasLimitBoundDivisor": "0x20",
+            "chainID": "0x100"
+        }"#;
+
+        let deserialized: Params = serde_json::from_str(s).unwrap();
+        assert_eq!(deserialized.chain_id, Some(Uint(U256::from(0x100))));
     }
 
     #[test]
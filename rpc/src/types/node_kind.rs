@@ -30,6 +30,10 @@ pub struct NodeKind {
     pub capability: Capability,
     /// Who the node is available to.
     pub availability: Availability,
+    /// Whether the node accepts AVM contract calls. Absent in descriptions of older nodes,
+    /// which only support FVM contracts.
+    #[serde(default)]
+    pub avm: bool,
 }
 
 /// Who the node is available to.
@@ -107,10 +111,25 @@ mod tests {
         let kind = NodeKind {
             capability: Capability::Full,
             availability: Availability::Public,
+            avm: true,
         };
-        let s = r#"{"capability":"full","availability":"public"}"#;
+        let s = r#"{"capability":"full","availability":"public","avm":true}"#;
 
         assert_eq!(serde_json::to_string(&kind).unwrap(), s);
         assert_eq!(serde_json::from_str::<NodeKind>(s).unwrap(), kind);
     }
+
+    #[test]
+    fn node_kind_without_avm() {
+        let s = r#"{"capability":"light","availability":"personal"}"#;
+
+        assert_eq!(
+            serde_json::from_str::<NodeKind>(s).unwrap(),
+            NodeKind {
+                capability: Capability::Light,
+                availability: Availability::Personal,
+                avm: false,
+            }
+        );
+    }
 }
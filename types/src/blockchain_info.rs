@@ -66,6 +66,26 @@ impl BlockChainInfo {
             )
         }
     }
+
+    /// Number of blocks the best block lags behind the best block known on the network.
+    pub fn blocks_behind(&self, network_best: BlockNumber) -> BlockNumber {
+        network_best.saturating_sub(self.best_block_number)
+    }
+
+    /// Progress, between 0 and 1, of filling the gap between the ancient blocks and the
+    /// first block of the best sequence. `None` if the chain has no gap.
+    pub fn sync_progress(&self) -> Option<f64> {
+        match (self.ancient_block_number, self.first_block_number) {
+            (Some(ancient), Some(first)) => {
+                if first <= 1 {
+                    return Some(1.0);
+                }
+                let target = first - 1;
+                Some(::std::cmp::min(ancient, target) as f64 / target as f64)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for BlockChainInfo {
@@ -73,3 +93,42 @@ impl fmt::Display for BlockChainInfo {
         write!(f, "#{}.{}", self.best_block_number, self.best_block_hash)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::BlockChainInfo;
+
+    fn info(best: u64, ancient: Option<u64>, first: Option<u64>) -> BlockChainInfo {
+        BlockChainInfo {
+            total_difficulty: Default::default(),
+            pending_total_difficulty: Default::default(),
+            genesis_hash: Default::default(),
+            best_block_hash: Default::default(),
+            best_block_number: best,
+            best_block_timestamp: 0,
+            ancient_block_hash: ancient.map(|_| Default::default()),
+            ancient_block_number: ancient,
+            first_block_hash: first.map(|_| Default::default()),
+            first_block_number: first,
+        }
+    }
+
+    #[test]
+    fn blocks_behind() {
+        let info = info(100, None, None);
+        assert_eq!(info.blocks_behind(150), 50);
+        assert_eq!(info.blocks_behind(100), 0);
+        assert_eq!(info.blocks_behind(50), 0);
+    }
+
+    #[test]
+    fn sync_progress() {
+        // no gap
+        assert_eq!(info(100, None, None).sync_progress(), None);
+        // ancient blocks 0..=25 of the 0..=50 gap are filled
+        assert_eq!(info(100, Some(25), Some(51)).sync_progress(), Some(0.5));
+        assert_eq!(info(100, Some(0), Some(51)).sync_progress(), Some(0.0));
+        assert_eq!(info(100, Some(50), Some(51)).sync_progress(), Some(1.0));
+        assert_eq!(info(100, Some(0), Some(1)).sync_progress(), Some(1.0));
+    }
+}
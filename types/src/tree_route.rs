@@ -34,3 +34,14 @@ pub struct TreeRoute {
     /// An index where best common ancestor would be.
     pub index: usize,
 }
+
+impl TreeRoute {
+    /// Blocks retracted on the way from `from` to the common ancestor.
+    pub fn retracted(&self) -> &[H256] { &self.blocks[..self.index] }
+
+    /// Blocks enacted on the way from the common ancestor to `to`.
+    pub fn enacted(&self) -> &[H256] { &self.blocks[self.index..] }
+
+    /// Returns true if the route both retracts and enacts blocks.
+    pub fn is_reorg(&self) -> bool { !self.retracted().is_empty() && !self.enacted().is_empty() }
+}
@@ -45,6 +45,11 @@ error_chain! {
             display("Invalid data"),
         }
 
+        InvalidLength(expected: usize, actual: usize) {
+            description("Invalid length"),
+            display("Invalid length: expected {} bytes, got {}", expected, actual),
+        }
+
         CallError {
             description("Call error"),
             display("Call error"),
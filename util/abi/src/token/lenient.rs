@@ -22,32 +22,69 @@
 
 use token::{Tokenizer, StrictTokenizer};
 use util::{pad_u32, pad_i32};
-use errors::Error;
+use errors::{Error, ErrorKind};
 
 /// Tries to parse string as a token. Does not require string to clearly represent the value.
+/// Hex values may contain whitespace and `_` separators, e.g. `12 34_56`, and strings may be
+/// wrapped in double quotes, which are stripped. A quoted `bytesN` value is packed from the
+/// UTF-8 bytes of the string instead of being parsed as hex, e.g. `"abc"`. Bools may also be
+/// given as `yes`/`no` or `on`/`off`, in any case.
 pub struct LenientTokenizer;
 
+/// Returns the contents of a value wrapped in double quotes.
+fn unquote(value: &str) -> Option<&str> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(&value[1..value.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Drops readability separators from a hex value.
+fn strip_separators(value: &str) -> String {
+    value
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace() && *ch != '_')
+        .collect()
+}
+
 impl Tokenizer for LenientTokenizer {
     fn tokenize_address(value: &str) -> Result<[u8; 32], Error> {
-        StrictTokenizer::tokenize_address(value)
+        StrictTokenizer::tokenize_any_address(&strip_separators(value))
     }
 
     fn tokenize_string(value: &str) -> Result<String, Error> {
-        StrictTokenizer::tokenize_string(value)
+        // quotes let strings hold commas and brackets inside arrays and param lists
+        StrictTokenizer::tokenize_string(unquote(value).unwrap_or(value))
     }
 
-    fn tokenize_bool(value: &str) -> Result<bool, Error> { StrictTokenizer::tokenize_bool(value) }
+    fn tokenize_bool(value: &str) -> Result<bool, Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "yes" | "on" => Ok(true),
+            "no" | "off" => Ok(false),
+            lowercase => StrictTokenizer::tokenize_bool(lowercase),
+        }
+    }
 
     fn tokenize_bytes(value: &str) -> Result<Vec<u8>, Error> {
-        StrictTokenizer::tokenize_bytes(value)
+        StrictTokenizer::tokenize_bytes(&strip_separators(value))
     }
 
     fn tokenize_fixed_bytes(value: &str, len: usize) -> Result<Vec<u8>, Error> {
-        StrictTokenizer::tokenize_fixed_bytes(value, len)
+        if let Some(text) = unquote(value) {
+            // byte-pack the string, right-padded with zeros
+            if text.len() > len {
+                return Err(ErrorKind::InvalidLength(len, text.len()).into());
+            }
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(len, 0);
+            return Ok(bytes);
+        }
+        StrictTokenizer::tokenize_fixed_bytes(&strip_separators(value), len)
     }
 
     fn tokenize_uint(value: &str) -> Result<[u8; 32], Error> {
-        let result = StrictTokenizer::tokenize_uint(value);
+        let result = StrictTokenizer::tokenize_uint(&strip_separators(value));
         if result.is_ok() {
             return result;
         }
@@ -57,7 +94,7 @@ impl Tokenizer for LenientTokenizer {
     }
 
     fn tokenize_int(value: &str) -> Result<[u8; 32], Error> {
-        let result = StrictTokenizer::tokenize_int(value);
+        let result = StrictTokenizer::tokenize_int(&strip_separators(value));
         if result.is_ok() {
             return result;
         }
@@ -113,6 +113,56 @@ pub trait Tokenizer {
         Ok(result)
     }
 
+    /// Tries to parse a comma-separated list of values, one for each of given types.
+    fn tokenize_param_list(params: &[ParamType], value: &str) -> Result<Vec<Token>, Error> {
+        let mut values = vec![];
+        let mut nested = 0isize;
+        let mut ignore = false;
+        let mut last_item = 0;
+        for (i, ch) in value.char_indices() {
+            match ch {
+                '[' if ignore == false => {
+                    nested += 1;
+                }
+                ']' if ignore == false => {
+                    nested -= 1;
+                    if nested < 0 {
+                        return Err(ErrorKind::InvalidData.into());
+                    }
+                }
+                '"' => {
+                    ignore = !ignore;
+                }
+                ',' if nested == 0 && ignore == false => {
+                    values.push(&value[last_item..i]);
+                    last_item = i + 1;
+                }
+                _ => (),
+            }
+        }
+        if nested != 0 || ignore {
+            return Err(ErrorKind::InvalidData.into());
+        }
+        if !value.is_empty() {
+            values.push(&value[last_item..]);
+        }
+
+        if values.len() != params.len() {
+            return Err(format!(
+                "Expected {} arguments, found {}",
+                params.len(),
+                values.len()
+            )
+            .into());
+        }
+
+        params
+            .iter()
+            .zip(values)
+            .map(|(param, value)| Self::tokenize(param, value))
+            .collect()
+    }
+
     /// Tries to parse a value as an address.
     fn tokenize_address(value: &str) -> Result<[u8; 32], Error>;
 
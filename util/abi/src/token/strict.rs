@@ -24,11 +24,20 @@ use hex::FromHex;
 use token::Tokenizer;
 use errors::{Error, ErrorKind};
 
+/// Leading bytes allowed in aion account addresses.
+const ADDRESS_PREFIXES: [u8; 1] = [0xa0];
+
 /// Tries to parse string as a token. Require string to clearly represent the value.
 pub struct StrictTokenizer;
 
-impl Tokenizer for StrictTokenizer {
-    fn tokenize_address(value: &str) -> Result<[u8; 32], Error> {
+impl StrictTokenizer {
+    /// Tries to parse a value, optionally `0x`-prefixed, as an address of any leading byte.
+    pub(crate) fn tokenize_any_address(value: &str) -> Result<[u8; 32], Error> {
+        let value = if value.starts_with("0x") {
+            &value[2..]
+        } else {
+            value
+        };
         let hex = try!(value.from_hex());
         match hex.len() == 32 {
             false => Err(ErrorKind::InvalidData.into()),
@@ -39,6 +48,16 @@ impl Tokenizer for StrictTokenizer {
             }
         }
     }
+}
+
+impl Tokenizer for StrictTokenizer {
+    fn tokenize_address(value: &str) -> Result<[u8; 32], Error> {
+        let address = try!(Self::tokenize_any_address(value));
+        match ADDRESS_PREFIXES.contains(&address[0]) {
+            true => Ok(address),
+            false => Err(ErrorKind::InvalidData.into()),
+        }
+    }
 
     fn tokenize_string(value: &str) -> Result<String, Error> { Ok(value.to_owned()) }
 
@@ -59,7 +78,7 @@ impl Tokenizer for StrictTokenizer {
         let hex = try!(value.from_hex());
         match hex.len() == len {
             true => Ok(hex),
-            false => Err(ErrorKind::InvalidData.into()),
+            false => Err(ErrorKind::InvalidLength(len, hex.len()).into()),
         }
     }
 
@@ -90,25 +109,40 @@ impl Tokenizer for StrictTokenizer {
 
 #[cfg(test)]
 mod tests {
-    use ParamType;
-    use token::{Token, Tokenizer, StrictTokenizer};
+    use {ParamType, Error, ErrorKind};
+    use token::{Token, Tokenizer, StrictTokenizer, LenientTokenizer};
 
     #[test]
     fn tokenize_address() {
+        let mut address = [0x11u8; 32];
+        address[0] = 0xa0;
         assert_eq!(
             StrictTokenizer::tokenize(
                 &ParamType::Address,
-                "1111111111111111111111111111111111111111111111111111111111111111"
+                "a011111111111111111111111111111111111111111111111111111111111111"
             )
             .unwrap(),
-            Token::Address([0x11u8; 32].into())
+            Token::Address(address.into())
         );
         assert_eq!(
             StrictTokenizer::tokenize(
                 &ParamType::Address,
-                "2222222222222222222222222222222222222222222222222222222222222222"
+                "0xa011111111111111111111111111111111111111111111111111111111111111"
             )
             .unwrap(),
+            Token::Address(address.into())
+        );
+    }
+
+    #[test]
+    fn tokenize_address_with_disallowed_prefix() {
+        let value = "2222222222222222222222222222222222222222222222222222222222222222";
+        match StrictTokenizer::tokenize_address(value) {
+            Err(Error(ErrorKind::InvalidData, _)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::Address, value).unwrap(),
             Token::Address([0x22u8; 32].into())
         );
     }
@@ -125,6 +159,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_quoted_string() {
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::String, "\"hello\"").unwrap(),
+            Token::String("hello".to_owned())
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::String, "hello").unwrap(),
+            Token::String("hello".to_owned())
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::String, "\"hello, world\"").unwrap(),
+            Token::String("hello, world".to_owned())
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(
+                &ParamType::Array(Box::new(ParamType::String)),
+                "[\"hello, world\",bye]"
+            )
+            .unwrap(),
+            Token::Array(vec![
+                Token::String("hello, world".to_owned()),
+                Token::String("bye".to_owned()),
+            ])
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize_param_list(
+                &[ParamType::String, ParamType::Bool],
+                "\"hello, world\",true"
+            )
+            .unwrap(),
+            vec![Token::String("hello, world".to_owned()), Token::Bool(true)]
+        );
+        // strict mode keeps the quotes
+        assert_eq!(
+            StrictTokenizer::tokenize(&ParamType::String, "\"hello\"").unwrap(),
+            Token::String("\"hello\"".to_owned())
+        );
+    }
+
     #[test]
     fn tokenize_bool() {
         assert_eq!(
@@ -145,6 +219,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_bool_synonyms() {
+        for value in &["yes", "on", "YES", "On", "TRUE"] {
+            assert_eq!(
+                LenientTokenizer::tokenize(&ParamType::Bool, value).unwrap(),
+                Token::Bool(true)
+            );
+            assert!(StrictTokenizer::tokenize(&ParamType::Bool, value).is_err());
+        }
+        for value in &["no", "off", "No", "OFF", "False"] {
+            assert_eq!(
+                LenientTokenizer::tokenize(&ParamType::Bool, value).unwrap(),
+                Token::Bool(false)
+            );
+            assert!(StrictTokenizer::tokenize(&ParamType::Bool, value).is_err());
+        }
+        for value in &["maybe", "y", "2", ""] {
+            match LenientTokenizer::tokenize_bool(value) {
+                Err(Error(ErrorKind::InvalidData, _)) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+            match StrictTokenizer::tokenize_bool(value) {
+                Err(Error(ErrorKind::InvalidData, _)) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
     #[test]
     fn tokenize_bytes() {
         assert_eq!(
@@ -157,6 +259,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_bytes_with_separators() {
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::Bytes, "12 34_56").unwrap(),
+            LenientTokenizer::tokenize(&ParamType::Bytes, "123456").unwrap()
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::FixedBytes(3), "12\t34_56").unwrap(),
+            Token::FixedBytes(vec![0x12, 0x34, 0x56])
+        );
+        assert!(StrictTokenizer::tokenize(&ParamType::Bytes, "12 34_56").is_err());
+        assert!(StrictTokenizer::tokenize(&ParamType::FixedBytes(3), "12 34_56").is_err());
+
+        let mut address = [0x11u8; 32];
+        address[0] = 0xa0;
+        let spaced = concat!(
+            "0xa011_1111 1111_1111 1111_1111 1111_1111",
+            " 1111_1111 1111_1111 1111_1111 1111_1111"
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::Address, spaced).unwrap(),
+            Token::Address(address.into())
+        );
+        assert!(StrictTokenizer::tokenize(&ParamType::Address, spaced).is_err());
+    }
+
     #[test]
     fn tokenize_fixed_bytes() {
         assert_eq!(
@@ -169,6 +297,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_fixed_bytes_length_mismatch() {
+        match StrictTokenizer::tokenize(&ParamType::FixedBytes(3), "1234") {
+            Err(Error(ErrorKind::InvalidLength(3, 2), _)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let err = StrictTokenizer::tokenize(&ParamType::FixedBytes(2), "123456").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid length: expected 2 bytes, got 3");
+        let err = LenientTokenizer::tokenize(&ParamType::FixedBytes(2), "\"abc\"").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid length: expected 2 bytes, got 3");
+    }
+
+    #[test]
+    fn tokenize_quoted_fixed_bytes() {
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::FixedBytes(5), "\"abc\"").unwrap(),
+            Token::FixedBytes(vec![b'a', b'b', b'c', 0, 0])
+        );
+        assert_eq!(
+            LenientTokenizer::tokenize(&ParamType::FixedBytes(3), "\"abc\"").unwrap(),
+            Token::FixedBytes(b"abc".to_vec())
+        );
+        assert!(LenientTokenizer::tokenize(&ParamType::FixedBytes(2), "\"abc\"").is_err());
+        assert!(StrictTokenizer::tokenize(&ParamType::FixedBytes(3), "\"abc\"").is_err());
+    }
+
     #[test]
     fn tokenize_uint() {
         assert_eq!(
@@ -254,4 +408,41 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn tokenize_param_list() {
+        let params = vec![
+            ParamType::Uint(128),
+            ParamType::Array(Box::new(ParamType::Bool)),
+            ParamType::String,
+        ];
+        assert_eq!(
+            StrictTokenizer::tokenize_param_list(
+                &params,
+                "1111111111111111111111111111111111111111111111111111111111111111,[true,0,1],hello"
+            )
+            .unwrap(),
+            vec![
+                Token::Uint([0x11u8; 32].into()),
+                Token::Array(vec![
+                    Token::Bool(true),
+                    Token::Bool(false),
+                    Token::Bool(true),
+                ]),
+                Token::String("hello".to_owned()),
+            ]
+        );
+        assert!(StrictTokenizer::tokenize_param_list(&[], "")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn tokenize_param_list_arity_mismatch() {
+        let params = vec![ParamType::Bool, ParamType::Array(Box::new(ParamType::Bool))];
+        assert!(StrictTokenizer::tokenize_param_list(&params, "true").is_err());
+        assert!(StrictTokenizer::tokenize_param_list(&params, "true,[true],false").is_err());
+        assert!(StrictTokenizer::tokenize_param_list(&params, "true,[true").is_err());
+        assert!(StrictTokenizer::tokenize_param_list(&params, "true,[true,false]").is_ok());
+    }
 }
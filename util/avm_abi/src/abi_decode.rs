@@ -0,0 +1,318 @@
+use std::fmt;
+
+/// A decoded token. Unlike `AbiToken`, it owns its data.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AbiDecoded {
+    UCHAR(u8),
+    BOOL(bool),
+    INT8(i8),
+    INT16(i16),
+    INT32(i32),
+    INT64(i64),
+    INT128(i128),
+    UINT128(u128),
+    FLOAT(f32),
+    DOUBLE(f64),
+    AUCHAR(Vec<u8>),
+    ABOOL(Vec<bool>),
+    AINT8(Vec<i8>),
+    AINT16(Vec<i16>),
+    AINT32(Vec<i32>),
+    AINT64(Vec<i64>),
+    AFLOAT(Vec<f32>),
+    ADOUBLE(Vec<f64>),
+    STRING(String),
+    ADDRESS([u8; 32]),
+}
+
+/// Error found while decoding tokens. Offsets are positions of the offending token
+/// in the decoded buffer.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DecodeError {
+    /// The buffer ends in the middle of the token at given offset.
+    Truncated(usize),
+    /// The type tag at given offset is unknown.
+    UnknownType {
+        offset: usize,
+        tag: u8,
+    },
+    /// The `BOOL` or `ABOOL` at given offset holds an invalid value.
+    InvalidBool(usize),
+    /// The `STRING` at given offset has a negative length or is not valid UTF-8.
+    InvalidString(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Truncated(offset) => write!(f, "Token at {} is truncated", offset),
+            DecodeError::UnknownType {
+                offset,
+                tag,
+            } => write!(f, "Unknown type 0x{:02x} at {}", tag, offset),
+            DecodeError::InvalidBool(offset) => write!(f, "Invalid bool at {}", offset),
+            DecodeError::InvalidString(offset) => write!(f, "Invalid string at {}", offset),
+        }
+    }
+}
+
+/// Decoder of the token encoding produced by `AVMEncoder`.
+pub struct AVMDecoder;
+
+impl AVMDecoder {
+    /// Decode the token at the start of `buf`, returning it with the number of bytes it takes.
+    pub fn decode(buf: &[u8]) -> Result<(AbiDecoded, usize), DecodeError> {
+        decode_at(buf, 0)
+    }
+
+    /// Decode every token of `buf`, e.g. a method name followed by its arguments.
+    /// The buffer must end exactly after the last token.
+    pub fn decode_all(buf: &[u8]) -> Result<Vec<AbiDecoded>, DecodeError> {
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+        while offset < buf.len() {
+            let (token, end) = decode_at(buf, offset)?;
+            tokens.push(token);
+            offset = end;
+        }
+        Ok(tokens)
+    }
+}
+
+// Decode the token starting at `offset`, returning it with the offset of the next one.
+fn decode_at(buf: &[u8], offset: usize) -> Result<(AbiDecoded, usize), DecodeError> {
+    let tag = *buf.get(offset).ok_or(DecodeError::Truncated(offset))?;
+    let start = offset + 1;
+    let take = |len: usize| -> Result<&[u8], DecodeError> {
+        buf.get(start..start + len).ok_or(DecodeError::Truncated(offset))
+    };
+
+    let (token, len) = match tag {
+        0x01 => (AbiDecoded::UCHAR(take(1)?[0]), 1),
+        0x02 => {
+            let value = match take(1)?[0] {
+                0x00 => false,
+                0x01 => true,
+                _ => return Err(DecodeError::InvalidBool(offset)),
+            };
+            (AbiDecoded::BOOL(value), 1)
+        }
+        0x03 => (AbiDecoded::INT8(take(1)?[0] as i8), 1),
+        0x04 => (AbiDecoded::INT16(read_be(take(2)?) as u16 as i16), 2),
+        0x05 => (AbiDecoded::INT32(read_be(take(4)?) as u32 as i32), 4),
+        0x06 => (AbiDecoded::INT64(read_be(take(8)?) as u64 as i64), 8),
+        0x07 => (AbiDecoded::FLOAT(f32::from_bits(read_be(take(4)?) as u32)), 4),
+        0x08 => (AbiDecoded::DOUBLE(f64::from_bits(read_be(take(8)?) as u64)), 8),
+        0x09 => (AbiDecoded::INT128(read_be(take(16)?) as i128), 16),
+        0x0a => (AbiDecoded::UINT128(read_be(take(16)?)), 16),
+        0x11..=0x18 => {
+            let count = read_be(take(2)?) as usize;
+            let width = match tag {
+                0x11..=0x13 => 1,
+                0x14 => 2,
+                0x15 | 0x17 => 4,
+                _ => 8,
+            };
+            let items = buf
+                .get(start + 2..start + 2 + count * width)
+                .ok_or(DecodeError::Truncated(offset))?;
+            (decode_array(tag, items, offset)?, 2 + count * width)
+        }
+        0x21 => {
+            let len = read_be(take(2)?) as u16 as i16;
+            if len < 0 {
+                return Err(DecodeError::InvalidString(offset));
+            }
+            let bytes = buf
+                .get(start + 2..start + 2 + len as usize)
+                .ok_or(DecodeError::Truncated(offset))?;
+            let value = String::from_utf8(bytes.to_vec())
+                .map_err(|_| DecodeError::InvalidString(offset))?;
+            (AbiDecoded::STRING(value), 2 + len as usize)
+        }
+        0x22 => {
+            let mut addr = [0u8; 32];
+            addr.copy_from_slice(take(32)?);
+            (AbiDecoded::ADDRESS(addr), 32)
+        }
+        _ => {
+            return Err(DecodeError::UnknownType {
+                offset: offset,
+                tag: tag,
+            })
+        }
+    };
+    Ok((token, start + len))
+}
+
+// Decode the elements of the array with given tag, `items` holding exactly all of them.
+fn decode_array(tag: u8, items: &[u8], offset: usize) -> Result<AbiDecoded, DecodeError> {
+    let token = match tag {
+        0x11 => AbiDecoded::AUCHAR(items.to_vec()),
+        0x12 => {
+            // the encoder writes `false` as 0x02
+            let values = items
+                .iter()
+                .map(|item| {
+                    match *item {
+                        0x01 => Ok(true),
+                        0x02 => Ok(false),
+                        _ => Err(DecodeError::InvalidBool(offset)),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            AbiDecoded::ABOOL(values)
+        }
+        0x13 => AbiDecoded::AINT8(items.iter().map(|item| *item as i8).collect()),
+        0x14 => AbiDecoded::AINT16(items.chunks(2).map(|c| read_be(c) as u16 as i16).collect()),
+        0x15 => AbiDecoded::AINT32(items.chunks(4).map(|c| read_be(c) as u32 as i32).collect()),
+        0x16 => AbiDecoded::AINT64(items.chunks(8).map(|c| read_be(c) as u64 as i64).collect()),
+        0x17 => {
+            AbiDecoded::AFLOAT(
+                items
+                    .chunks(4)
+                    .map(|c| f32::from_bits(read_be(c) as u32))
+                    .collect(),
+            )
+        }
+        _ => {
+            AbiDecoded::ADOUBLE(
+                items
+                    .chunks(8)
+                    .map(|c| f64::from_bits(read_be(c) as u64))
+                    .collect(),
+            )
+        }
+    };
+    Ok(token)
+}
+
+fn read_be(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(0u128, |value, byte| (value << 8) | *byte as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi_token::{AbiToken, AVMEncoder, encode_call};
+
+    #[test]
+    fn decode_single_tokens() {
+        let tokens = vec![
+            (AbiToken::UCHAR(0xff), AbiDecoded::UCHAR(0xff)),
+            (AbiToken::BOOL(true), AbiDecoded::BOOL(true)),
+            (AbiToken::INT8(-1), AbiDecoded::INT8(-1)),
+            (AbiToken::INT16(-2), AbiDecoded::INT16(-2)),
+            (AbiToken::INT32(3), AbiDecoded::INT32(3)),
+            (AbiToken::INT64(-4), AbiDecoded::INT64(-4)),
+            (AbiToken::INT128(-5), AbiDecoded::INT128(-5)),
+            (AbiToken::UINT128(6), AbiDecoded::UINT128(6)),
+            (AbiToken::FLOAT(1.5), AbiDecoded::FLOAT(1.5)),
+            (AbiToken::DOUBLE(-2.5), AbiDecoded::DOUBLE(-2.5)),
+            (
+                AbiToken::STRING("sayHello".to_string()),
+                AbiDecoded::STRING("sayHello".to_string()),
+            ),
+            (AbiToken::ADDRESS([0x11; 32]), AbiDecoded::ADDRESS([0x11; 32])),
+        ];
+        for (token, expected) in tokens {
+            let encoded = token.encode();
+            assert_eq!(AVMDecoder::decode(&encoded), Ok((expected, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn decode_all_call_payload() {
+        let payload = encode_call(
+            "transfer",
+            &[
+                AbiToken::ADDRESS([0xa0; 32]),
+                AbiToken::INT64(100),
+                AbiToken::BOOL(false),
+            ],
+        );
+        assert_eq!(
+            AVMDecoder::decode_all(&payload),
+            Ok(vec![
+                AbiDecoded::STRING("transfer".to_string()),
+                AbiDecoded::ADDRESS([0xa0; 32]),
+                AbiDecoded::INT64(100),
+                AbiDecoded::BOOL(false),
+            ])
+        );
+        assert_eq!(AVMDecoder::decode_all(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn decode_arrays() {
+        let tokens = vec![
+            (AbiToken::AUCHAR(&[1, 2]), AbiDecoded::AUCHAR(vec![1, 2])),
+            (AbiToken::ABOOL(&[true, false]), AbiDecoded::ABOOL(vec![true, false])),
+            (AbiToken::AINT8(&[-1, 2]), AbiDecoded::AINT8(vec![-1, 2])),
+            (AbiToken::AINT16(&[-3]), AbiDecoded::AINT16(vec![-3])),
+            (AbiToken::AINT32(&[4, -5]), AbiDecoded::AINT32(vec![4, -5])),
+            (AbiToken::AINT64(&[-6]), AbiDecoded::AINT64(vec![-6])),
+            (AbiToken::AFLOAT(&[1.5]), AbiDecoded::AFLOAT(vec![1.5])),
+            (AbiToken::ADOUBLE(&[-2.5, 0.0]), AbiDecoded::ADOUBLE(vec![-2.5, 0.0])),
+        ];
+        for (token, expected) in tokens {
+            let encoded = token.encode();
+            assert_eq!(AVMDecoder::decode(&encoded), Ok((expected, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn decode_empty_arrays() {
+        let empty = AbiToken::AINT32(&[]).encode();
+        assert_eq!(AVMDecoder::decode(&empty), Ok((AbiDecoded::AINT32(vec![]), 3)));
+
+        // an empty array followed by more tokens stays distinguishable from a one-element one
+        let payload = encode_call("f", &[AbiToken::AINT32(&[]), AbiToken::INT32(9)]);
+        assert_eq!(
+            AVMDecoder::decode_all(&payload),
+            Ok(vec![
+                AbiDecoded::STRING("f".to_string()),
+                AbiDecoded::AINT32(vec![]),
+                AbiDecoded::INT32(9),
+            ])
+        );
+        let payload = encode_call("f", &[AbiToken::AINT32(&[9])]);
+        assert_eq!(
+            AVMDecoder::decode_all(&payload),
+            Ok(vec![AbiDecoded::STRING("f".to_string()), AbiDecoded::AINT32(vec![9])])
+        );
+
+        // the bare type byte is a truncated array, not an empty one
+        assert_eq!(AVMDecoder::decode(&[0x15]), Err(DecodeError::Truncated(0)));
+    }
+
+    #[test]
+    fn decode_all_rejects_truncation_and_garbage() {
+        let payload = encode_call("transfer", &[AbiToken::INT64(100)]);
+        // the INT64 starts right after the 11 bytes of the method name
+        assert_eq!(
+            AVMDecoder::decode_all(&payload[..payload.len() - 1]),
+            Err(DecodeError::Truncated(11))
+        );
+        assert_eq!(AVMDecoder::decode_all(&payload[..5]), Err(DecodeError::Truncated(0)));
+
+        let mut garbage = payload.clone();
+        garbage.push(0xee);
+        assert_eq!(
+            AVMDecoder::decode_all(&garbage),
+            Err(DecodeError::UnknownType {
+                offset: payload.len(),
+                tag: 0xee,
+            })
+        );
+        assert_eq!(
+            AVMDecoder::decode_all(&AbiToken::AUCHAR(&[1, 2]).encode()[..4]),
+            Err(DecodeError::Truncated(0))
+        );
+        assert_eq!(AVMDecoder::decode_all(&[0x02, 0x02]), Err(DecodeError::InvalidBool(0)));
+        assert_eq!(
+            AVMDecoder::decode_all(&[0x12, 0x00, 0x01, 0x00]),
+            Err(DecodeError::InvalidBool(0))
+        );
+    }
+}
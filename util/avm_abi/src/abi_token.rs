@@ -4,6 +4,9 @@ use std::mem;
 
 pub trait ToBytes {
     fn to_vm_bytes(&self) -> Vec<u8>;
+
+    /// Append the vm bytes to `out`.
+    fn write_vm_bytes(&self, out: &mut Vec<u8>) { out.extend_from_slice(&self.to_vm_bytes()); }
 }
 
 pub trait FromBytes {
@@ -51,6 +54,12 @@ macro_rules! format_as_bytes {
 
                 bytes.to_vec()
             }
+
+            fn write_vm_bytes(&self, out: &mut Vec<u8>) {
+                let bytes: [u8; $len] = unsafe { mem::transmute(self.to_be()) };
+
+                out.extend_from_slice(&bytes);
+            }
         }
     };
 }
@@ -61,6 +70,8 @@ format_as_bytes!(u32, 4);
 format_as_bytes!(i32, 4);
 format_as_bytes!(u64, 8);
 format_as_bytes!(i64, 8);
+format_as_bytes!(u128, 16);
+format_as_bytes!(i128, 16);
 format_as_bytes!(f32, 4);
 format_as_bytes!(f64, 8);
 
@@ -71,6 +82,8 @@ pub enum AbiToken<'a> {
     INT16(i16),
     INT32(i32),
     INT64(i64),
+    INT128(i128),
+    UINT128(u128),
     FLOAT(f32),
     DOUBLE(f64),
     AUCHAR(&'a [u8]),
@@ -84,15 +97,67 @@ pub enum AbiToken<'a> {
     STRING(String),
     // METHOD(String),
     ADDRESS([u8; 32]),
+    /// Pre-encoded bytes, e.g. a nested call, appended verbatim. This bypasses type tagging:
+    /// formatting the bytes as valid tokens is the caller's responsibility.
+    RAW(&'a [u8]),
 }
 
 pub trait AVMEncoder {
-    fn encode(&self) -> Vec<u8>;
+    /// Exact length of the encoding, in bytes.
+    fn encoded_len(&self) -> usize;
+
+    /// Append the encoding to `out`.
+    ///
+    /// Panics if an array has more than 65535 elements or a string more than 32767 bytes,
+    /// whose length can't be framed.
+    fn encode_into(&self, out: &mut Vec<u8>);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut res);
+        res
+    }
+}
+
+/// Write the two byte element count framing an array.
+fn write_array_len(len: usize, res: &mut Vec<u8>) {
+    assert!(
+        len <= ::std::u16::MAX as usize,
+        "array of {} elements is too long to encode",
+        len
+    );
+    (len as u16).write_vm_bytes(res);
 }
 
 impl<'a> AVMEncoder for AbiToken<'a> {
-    fn encode(&self) -> Vec<u8> {
-        let mut res = Vec::new();
+    fn encoded_len(&self) -> usize {
+        // every token but raw bytes starts with a one byte type tag
+        let tag_len = match *self {
+            AbiToken::RAW(_) => 0,
+            _ => 1,
+        };
+        tag_len + match *self {
+            AbiToken::UCHAR(_) | AbiToken::BOOL(_) | AbiToken::INT8(_) => 1,
+            AbiToken::INT16(_) => 2,
+            AbiToken::INT32(_) | AbiToken::FLOAT(_) => 4,
+            AbiToken::INT64(_) | AbiToken::DOUBLE(_) => 8,
+            AbiToken::INT128(_) | AbiToken::UINT128(_) => 16,
+            // arrays are framed with a two byte element count
+            AbiToken::AUCHAR(v) => 2 + v.len(),
+            AbiToken::ABOOL(v) => 2 + v.len(),
+            AbiToken::AINT8(v) => 2 + v.len(),
+            AbiToken::AINT16(v) => 2 + v.len() * 2,
+            AbiToken::AINT32(v) => 2 + v.len() * 4,
+            AbiToken::AINT64(v) => 2 + v.len() * 8,
+            AbiToken::AFLOAT(v) => 2 + v.len() * 4,
+            AbiToken::ADOUBLE(v) => 2 + v.len() * 8,
+            AbiToken::STRING(ref v) => 2 + v.len(),
+            AbiToken::ADDRESS(ref addr) => addr.len(),
+            AbiToken::RAW(v) => v.len(),
+        }
+    }
+
+    fn encode_into(&self, res: &mut Vec<u8>) {
         match *self {
             AbiToken::UCHAR(v) => {
                 res.push(0x01);
@@ -112,32 +177,40 @@ impl<'a> AVMEncoder for AbiToken<'a> {
             }
             AbiToken::INT16(v) => {
                 res.push(0x04);
-                res.append(&mut v.to_vm_bytes())
+                v.write_vm_bytes(res);
             }
             AbiToken::INT32(v) => {
                 res.push(0x05);
-                res.append(&mut v.to_vm_bytes())
+                v.write_vm_bytes(res);
             }
             AbiToken::INT64(v) => {
                 res.push(0x06);
-                res.append(&mut v.to_vm_bytes())
+                v.write_vm_bytes(res);
+            }
+            AbiToken::INT128(v) => {
+                res.push(0x09);
+                v.write_vm_bytes(res);
+            }
+            AbiToken::UINT128(v) => {
+                res.push(0x0a);
+                v.write_vm_bytes(res);
             }
             AbiToken::FLOAT(v) => {
                 res.push(0x07);
-                res.append(&mut v.to_vm_bytes())
+                v.write_vm_bytes(res);
             }
             AbiToken::DOUBLE(v) => {
                 res.push(0x08);
-                res.append(&mut v.to_vm_bytes())
+                v.write_vm_bytes(res);
             }
             AbiToken::AUCHAR(v) => {
                 res.push(0x11);
-                for item in v {
-                    res.push(*item)
-                }
+                write_array_len(v.len(), res);
+                res.extend_from_slice(v);
             }
             AbiToken::ABOOL(v) => {
                 res.push(0x12);
+                write_array_len(v.len(), res);
                 for item in v {
                     if *item {
                         res.push(0x01)
@@ -148,44 +221,55 @@ impl<'a> AVMEncoder for AbiToken<'a> {
             }
             AbiToken::AINT8(v) => {
                 res.push(0x13);
+                write_array_len(v.len(), res);
                 for item in v {
                     res.push(*item as u8)
                 }
             }
             AbiToken::AINT16(v) => {
                 res.push(0x14);
+                write_array_len(v.len(), res);
                 for item in v {
-                    res.append(&mut item.to_vm_bytes());
+                    item.write_vm_bytes(res);
                 }
             }
             AbiToken::AINT32(v) => {
                 res.push(0x15);
+                write_array_len(v.len(), res);
                 for item in v {
-                    res.append(&mut item.to_vm_bytes());
+                    item.write_vm_bytes(res);
                 }
             }
             AbiToken::AINT64(v) => {
                 res.push(0x16);
+                write_array_len(v.len(), res);
                 for item in v {
-                    res.append(&mut item.to_vm_bytes());
+                    item.write_vm_bytes(res);
                 }
             }
             AbiToken::AFLOAT(v) => {
                 res.push(0x17);
+                write_array_len(v.len(), res);
                 for item in v {
-                    res.append(&mut item.to_vm_bytes());
+                    item.write_vm_bytes(res);
                 }
             }
             AbiToken::ADOUBLE(v) => {
                 res.push(0x18);
+                write_array_len(v.len(), res);
                 for item in v {
-                    res.append(&mut item.to_vm_bytes())
+                    item.write_vm_bytes(res);
                 }
             }
             AbiToken::STRING(ref v) => {
                 res.push(0x21);
-                res.append(&mut (v.len() as i16).to_vm_bytes());
-                res.append(&mut v.clone().into_bytes());
+                assert!(
+                    v.len() <= ::std::i16::MAX as usize,
+                    "string of {} bytes is too long to encode",
+                    v.len()
+                );
+                (v.len() as i16).write_vm_bytes(res);
+                res.extend_from_slice(v.as_bytes());
             }
             // AbiToken::METHOD(ref s) => {
             //     res.push(0x21);
@@ -196,10 +280,23 @@ impl<'a> AVMEncoder for AbiToken<'a> {
                 res.push(0x22);
                 res.extend(addr.iter());
             }
+            AbiToken::RAW(v) => res.extend_from_slice(v),
         }
+    }
+}
 
-        res
+/// Encode a call payload: the method name as a `STRING` token followed by `args` in order.
+pub fn encode_call(method: &str, args: &[AbiToken]) -> Vec<u8> {
+    let method = AbiToken::STRING(method.to_string());
+    let len = args
+        .iter()
+        .fold(method.encoded_len(), |len, arg| len + arg.encoded_len());
+    let mut res = Vec::with_capacity(len);
+    method.encode_into(&mut res);
+    for arg in args {
+        arg.encode_into(&mut res);
     }
+    res
 }
 
 #[cfg(test)]
@@ -228,7 +325,7 @@ mod tests {
         data_0 = AbiToken::FLOAT(1.0);
         assert_eq!(data_0.encode(), vec![0x07, 0x3f, 0x80, 0x00, 0x00]);
         data_0 = AbiToken::AFLOAT(&[1.0, 2.0]);
-        assert_eq!(data_0.encode(), vec![23, 63, 128, 0, 0, 64, 0, 0, 0]);
+        assert_eq!(data_0.encode(), vec![23, 0, 2, 63, 128, 0, 0, 64, 0, 0, 0]);
         data_0 = AbiToken::DOUBLE(1.0);
         assert_eq!(
             data_0.encode(),
@@ -237,7 +334,127 @@ mod tests {
         data_0 = AbiToken::ADOUBLE(&[1.0, 2.0]);
         assert_eq!(
             data_0.encode(),
-            vec![24, 63, 240, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0]
+            vec![24, 0, 2, 63, 240, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn encode_128_bit_integers() {
+        let value = 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10u128;
+        assert_eq!(
+            AbiToken::UINT128(value).encode(),
+            vec![0x0a, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]
+        );
+        assert_eq!(
+            AbiToken::INT128(value as i128).encode(),
+            vec![0x09, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]
+        );
+
+        let mut minus_two = vec![0x09];
+        minus_two.extend_from_slice(&[0xff; 15]);
+        minus_two.push(0xfe);
+        assert_eq!(AbiToken::INT128(-2).encode(), minus_two);
+
+        let mut min = vec![0x09, 0x80];
+        min.extend_from_slice(&[0x00; 15]);
+        assert_eq!(AbiToken::INT128(i128::min_value()).encode(), min);
+    }
+
+    #[test]
+    fn encode_empty_arrays() {
+        assert_eq!(AbiToken::AINT32(&[]).encode(), vec![0x15, 0x00, 0x00]);
+        assert_eq!(AbiToken::AUCHAR(&[]).encode(), vec![0x11, 0x00, 0x00]);
+        assert_eq!(AbiToken::AINT32(&[7]).encode(), vec![0x15, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07]);
+    }
+
+    #[test]
+    fn encode_longest_array() {
+        let v = vec![0u8; 0xffff];
+        let encoded = AbiToken::AUCHAR(&v).encode();
+        assert_eq!(&encoded[..3], &[0x11, 0xff, 0xff]);
+        assert_eq!(encoded.len(), AbiToken::AUCHAR(&v).encoded_len());
+    }
+
+    #[test]
+    #[should_panic(expected = "too long to encode")]
+    fn encode_oversized_array() {
+        let v = vec![0i32; 0x10000];
+        AbiToken::AINT32(&v).encode();
+    }
+
+    #[test]
+    #[should_panic(expected = "too long to encode")]
+    fn encode_oversized_string() {
+        AbiToken::STRING("a".repeat(0x8000)).encode();
+    }
+
+    #[test]
+    fn encode_raw() {
+        let nested = encode_call("inner", &[AbiToken::INT32(7)]);
+        assert_eq!(AbiToken::RAW(&nested).encode(), nested);
+
+        let mut res = vec![0xaa];
+        AbiToken::RAW(&[0x01, 0x02, 0x03]).encode_into(&mut res);
+        assert_eq!(res, vec![0xaa, 0x01, 0x02, 0x03]);
+
+        assert_eq!(
+            encode_call("outer", &[AbiToken::RAW(&nested)]),
+            [
+                AbiToken::STRING("outer".to_string()).encode(),
+                nested.clone()
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn encoded_len() {
+        let tokens = vec![
+            AbiToken::UCHAR(0x01),
+            AbiToken::BOOL(true),
+            AbiToken::INT8(-1),
+            AbiToken::INT16(-2),
+            AbiToken::INT32(3),
+            AbiToken::INT64(4),
+            AbiToken::INT128(-5),
+            AbiToken::UINT128(6),
+            AbiToken::FLOAT(1.0),
+            AbiToken::DOUBLE(2.0),
+            AbiToken::AUCHAR(&[1, 2, 3]),
+            AbiToken::ABOOL(&[true, false]),
+            AbiToken::AINT8(&[1, -1]),
+            AbiToken::AINT16(&[1, 2, 3]),
+            AbiToken::AINT32(&[1, 2]),
+            AbiToken::AINT64(&[1]),
+            AbiToken::AFLOAT(&[1.0, 2.0]),
+            AbiToken::ADOUBLE(&[1.0, 2.0]),
+            AbiToken::AINT64(&[]),
+            AbiToken::STRING("sayHello".to_string()),
+            AbiToken::STRING(String::new()),
+            AbiToken::ADDRESS([0x11; 32]),
+            AbiToken::RAW(&[0x01, 0x02]),
+            AbiToken::RAW(&[]),
+        ];
+
+        let mut all = Vec::new();
+        for token in &tokens {
+            assert_eq!(token.encoded_len(), token.encode().len());
+            token.encode_into(&mut all);
+        }
+        let expected: Vec<u8> = tokens.iter().flat_map(|t| t.encode()).collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn encode_call_payload() {
+        let payload = encode_call("sayHello", &[AbiToken::UCHAR(0xff)]);
+        assert_eq!(
+            payload,
+            vec![0x21, 0x00, 0x08, 0x73, 0x61, 0x79, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x01, 0xff]
+        );
+        assert_eq!(
+            encode_call("sayHello", &[]),
+            AbiToken::STRING("sayHello".to_string()).encode()
         );
     }
 
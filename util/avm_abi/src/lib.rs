@@ -1,4 +1,6 @@
 // mod abi_bytes;
+mod abi_decode;
 mod abi_token;
 
-pub use abi_token::{AbiToken, AVMEncoder, ToBytes, FromBytes};
+pub use abi_decode::{AbiDecoded, AVMDecoder, DecodeError};
+pub use abi_token::{AbiToken, AVMEncoder, ToBytes, FromBytes, encode_call};
@@ -73,7 +73,8 @@ static COMMON_RLPS: &'static [&'static [u8]] = &[
     ],
 ];
 
-static INVALID_RLPS: &'static [&'static [u8]] = &[
+/// Invalid RLPs standing in for swapped RLPs.
+pub static INVALID_RLPS: &'static [&'static [u8]] = &[
     &[0x81, 0x0],
     &[0x81, 0x1],
     &[0x81, 0x2],
@@ -15,7 +15,10 @@ use std::cmp;
 use std::collections::HashMap;
 use elastic_array::ElasticArray1024;
 use rlp::{UntrustedRlp, RlpStream};
-use common::{SNAPSHOT_SWAPPER, BLOCKS_SWAPPER};
+use common::{SNAPSHOT_SWAPPER, BLOCKS_SWAPPER, INVALID_RLPS};
+
+/// Maximum number of RLPs a `DictionarySwapper` can swap.
+pub const MAX_DICTIONARY_LEN: usize = 0x7e;
 
 pub fn snapshot_swapper() -> &'static Swapper<'static> { &SNAPSHOT_SWAPPER as &Swapper }
 
@@ -111,3 +114,42 @@ impl<'a> Decompressor for Swapper<'a> {
 impl<'a> Compressor for Swapper<'a> {
     fn compressed(&self, rlp: &[u8]) -> Option<&[u8]> { self.rlp_to_compressed.get(rlp).cloned() }
 }
+
+/// Swapper owning its RLPs, for dictionaries only known at runtime.
+/// Like the built-in swappers, the RLPs are swapped for invalid RLPs.
+pub struct DictionarySwapper {
+    compressed_to_rlp: HashMap<&'static [u8], Vec<u8>>,
+    rlp_to_compressed: HashMap<Vec<u8>, &'static [u8]>,
+}
+
+impl DictionarySwapper {
+    /// Construct a swapper from a list of common RLPs
+    pub fn new(rlps_to_swap: &[Vec<u8>]) -> Self {
+        if rlps_to_swap.len() > MAX_DICTIONARY_LEN {
+            panic!("Invalid usage, only {} RLPs can be swappable.", MAX_DICTIONARY_LEN);
+        }
+
+        let mut compressed_to_rlp = HashMap::with_capacity(rlps_to_swap.len());
+        let mut rlp_to_compressed = HashMap::with_capacity(rlps_to_swap.len());
+
+        for (rlp, &compressed) in rlps_to_swap.iter().zip(INVALID_RLPS.iter()) {
+            compressed_to_rlp.insert(compressed, rlp.clone());
+            rlp_to_compressed.insert(rlp.clone(), compressed);
+        }
+
+        DictionarySwapper {
+            compressed_to_rlp,
+            rlp_to_compressed,
+        }
+    }
+}
+
+impl Decompressor for DictionarySwapper {
+    fn decompressed(&self, compressed: &[u8]) -> Option<&[u8]> {
+        self.compressed_to_rlp.get(compressed).map(|rlp| &rlp[..])
+    }
+}
+
+impl Compressor for DictionarySwapper {
+    fn compressed(&self, rlp: &[u8]) -> Option<&[u8]> { self.rlp_to_compressed.get(rlp).cloned() }
+}
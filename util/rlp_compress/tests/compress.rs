@@ -23,7 +23,8 @@
 extern crate rlp_compress;
 
 use rlp_compress::{
-    compress, decompress, Swapper, snapshot_swapper, blocks_swapper, Compressor, Decompressor,
+    compress, decompress, Swapper, DictionarySwapper, snapshot_swapper, blocks_swapper, Compressor,
+    Decompressor,
 };
 
 #[test]
@@ -39,6 +40,21 @@ fn invalid_rlp_swapper() {
     assert_eq!(Some(to_swap[1]), swapper.decompressed(invalid_rlp[1]));
 }
 
+#[test]
+fn dictionary_swapper() {
+    let cat = vec![0x83, b'c', b'a', b't'];
+    let dog = vec![0x83, b'd', b'o', b'g'];
+    let swapper = DictionarySwapper::new(&[cat.clone(), dog.clone()]);
+    assert_eq!(Some(&[0x81, 0x00][..]), swapper.compressed(&cat));
+    assert_eq!(None, swapper.compressed(&[0x83, b'b', b'a', b't']));
+    assert_eq!(Some(&dog[..]), swapper.decompressed(&[0x81, 0x01]));
+
+    let list = vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g'];
+    let compressed = compress(&list, &swapper);
+    assert_eq!(compressed.to_vec(), vec![0xc4, 0x81, 0x00, 0x81, 0x01]);
+    assert_eq!(decompress(&compressed, &swapper).to_vec(), list);
+}
+
 #[test]
 fn simple_compression() {
     let basic_account_rlp = vec![